@@ -2,6 +2,8 @@
 extern crate criterion;
 
 use criterion::Criterion;
+#[cfg(feature = "compiled")]
+use criterion::Fun;
 use nessim::SimulationState;
 use std::fs::File;
 
@@ -17,5 +19,30 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
+/// The compiled tables against the interpreter, from the same power-on state.
+#[cfg(feature = "compiled")]
+fn compiled_benchmark(c: &mut Criterion) {
+    let mut sim = SimulationState::new();
+    sim.load_rom(&mut File::open("test_data/scanline.nes").unwrap());
+    let modes = [("Interpreted", false), ("Compiled", true)]
+        .iter()
+        .map(|(name, compiled)| {
+            let mut sim = sim.clone();
+            sim.set_compiled_tables(*compiled);
+            Fun::new(name, move |b, _: &()| {
+                b.iter(|| {
+                    for _ in 0..100 {
+                        sim.half_step()
+                    }
+                })
+            })
+        })
+        .collect();
+    c.bench_functions("100 Half-Steps by Group Resolution", modes, ());
+}
+
+#[cfg(not(feature = "compiled"))]
 criterion_group!(benches, criterion_benchmark);
+#[cfg(feature = "compiled")]
+criterion_group!(benches, criterion_benchmark, compiled_benchmark);
 criterion_main!(benches);
@@ -0,0 +1,63 @@
+use crate::{
+    channel_components::{LOCAL_GND, LOCAL_PWR},
+    netlist::Netlist,
+};
+use std::io::{self, Write};
+
+/// Entries written per line of a table, to keep the generated file readable.
+const ENTRIES_PER_LINE: usize = 8;
+
+/// Write the channel tables of `netlist` as constants: `NODE_CHANNELS` holding the start and length
+/// of each node's run of `CHANNELS` and its index within its component, `CHANNELS` holding each
+/// channel's transistor and the node on the other side, and `VISITED_WORDS`, the words of a bit set
+/// with room for every node of the largest component.
+pub fn write_tables<W: Write>(netlist: &Netlist, out: &mut W) -> io::Result<()> {
+    let components = &netlist.components;
+
+    writeln!(
+        out,
+        "// Generated by build/codegen.rs from the netlist in data/. Do not edit."
+    )?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "const VISITED_WORDS: usize = {};",
+        components.largest_len().div_ceil(64)
+    )?;
+
+    let mut node_channels = Vec::with_capacity(netlist.node_count());
+    let mut channels = Vec::new();
+    for node_number in 0..netlist.node_count() as u16 {
+        let component = components.component_of(node_number);
+        let local_index = components.local_index(node_number);
+        let start = channels.len();
+        let component_nodes = components.nodes(component);
+        for (transistor, neighbor) in components.channels(component, local_index) {
+            let neighbor = match *neighbor {
+                LOCAL_GND => String::from("NODE_GND"),
+                LOCAL_PWR => String::from("NODE_PWR"),
+                neighbor => component_nodes[neighbor as usize].to_string(),
+            };
+            channels.push(format!("({}, {})", transistor, neighbor));
+        }
+        node_channels.push(format!(
+            "({}, {}, {})",
+            start,
+            channels.len() - start,
+            local_index
+        ));
+    }
+
+    writeln!(out)?;
+    write_table(out, "NODE_CHANNELS", "(u32, u16, u16)", &node_channels)?;
+    writeln!(out)?;
+    write_table(out, "CHANNELS", "(u16, u16)", &channels)
+}
+
+fn write_table<W: Write>(out: &mut W, name: &str, ty: &str, entries: &[String]) -> io::Result<()> {
+    writeln!(out, "static {}: [{}; {}] = [", name, ty, entries.len())?;
+    for line in entries.chunks(ENTRIES_PER_LINE) {
+        writeln!(out, "    {},", line.join(", "))?;
+    }
+    writeln!(out, "];")
+}
@@ -0,0 +1,53 @@
+//! Generates the channel tables used by the `compiled` feature from the netlist in `data/`.
+//!
+//! The netlist is loaded with the same preprocessor the simulation uses, so the generated tables
+//! always matches the nodes and transistors the interpreter would build.
+
+// Only the loading half of the shared modules is needed here.
+#![allow(dead_code)]
+
+#[path = "../src/bit_set.rs"]
+mod bit_set;
+#[path = "../src/channel_components.rs"]
+mod channel_components;
+mod codegen;
+#[path = "../src/components.rs"]
+mod components;
+#[path = "../src/consts.rs"]
+mod consts;
+#[path = "../src/netlist.rs"]
+mod netlist;
+#[path = "../src/preprocessor/mod.rs"]
+mod preprocessor;
+
+use crate::netlist::Netlist;
+use std::{
+    env,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+fn main() {
+    for path in &[
+        "build",
+        "data",
+        "src/bit_set.rs",
+        "src/channel_components.rs",
+        "src/components.rs",
+        "src/consts.rs",
+        "src/netlist.rs",
+        "src/preprocessor/mod.rs",
+    ] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+
+    if env::var_os("CARGO_FEATURE_COMPILED").is_none() {
+        return;
+    }
+
+    let out_path = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("compiled_netlist.rs");
+    let mut out = BufWriter::new(File::create(out_path).unwrap());
+    codegen::write_tables(&Netlist::load(), &mut out).unwrap();
+    out.flush().unwrap();
+}
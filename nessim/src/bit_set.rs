@@ -0,0 +1,82 @@
+/// A fixed-size set of bits packed into 64-bit words.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        BitSet {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> bool {
+        self.words[index >> 6] & (1 << (index & 63)) != 0
+    }
+
+    #[inline]
+    pub fn set(&mut self, index: usize) {
+        self.words[index >> 6] |= 1 << (index & 63);
+    }
+
+    #[inline]
+    pub fn clear(&mut self, index: usize) {
+        self.words[index >> 6] &= !(1 << (index & 63));
+    }
+
+    #[inline]
+    pub fn assign(&mut self, index: usize, value: bool) {
+        if value {
+            self.set(index)
+        } else {
+            self.clear(index)
+        }
+    }
+
+    pub fn set_all(&mut self) {
+        self.words.iter_mut().for_each(|w| *w = !0);
+        let tail_bits = self.len % 64;
+        if tail_bits != 0 {
+            if let Some(last) = self.words.last_mut() {
+                *last = (1 << tail_bits) - 1;
+            }
+        }
+    }
+
+    pub fn clear_all(&mut self) {
+        self.words.iter_mut().for_each(|w| *w = 0);
+    }
+}
+
+#[test]
+fn test_set_and_clear() {
+    let mut set = BitSet::new(130);
+    assert!(!set.get(0));
+    set.set(0);
+    set.set(64);
+    set.set(129);
+    assert!(set.get(0));
+    assert!(!set.get(1));
+    assert!(set.get(64));
+    assert!(set.get(129));
+    set.clear(64);
+    assert!(!set.get(64));
+    set.assign(63, true);
+    assert!(set.get(63));
+    set.assign(63, false);
+    assert!(!set.get(63));
+}
+
+#[test]
+fn test_set_all_and_clear_all() {
+    let mut set = BitSet::new(70);
+    set.set_all();
+    assert!((0..70).all(|i| set.get(i)));
+    assert_eq!(0b11_1111, set.words[1]);
+    set.clear_all();
+    assert!((0..70).all(|i| !set.get(i)));
+}
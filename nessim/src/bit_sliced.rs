@@ -0,0 +1,681 @@
+use crate::{
+    consts::*,
+    event_log::{PpuEventKind, PpuEventLog},
+    frame_counter::{FrameCounter, VISIBLE_SCANLINES},
+    memory::Memory,
+    netlist::Netlist,
+    ntsc::{CompositeFrame, SUBCARRIER_PHASES},
+    palette::{OutputMode, Palette},
+    power_on::PowerOnOptions,
+    SimulationState,
+};
+use std::{mem, sync::Arc};
+
+/// The number of simulations a `BitSlicedSimulation` advances together.
+pub const LANES: usize = 64;
+
+const ALL_LANES: u64 = !0;
+
+/// The per-lane counterpart of everything in `SimulationState` that lives outside the netlist.
+#[derive(Clone)]
+struct Lane {
+    step_cycle_count: u8,
+    prev_ppu_ale: bool,
+    prev_ppu_write: bool,
+    prev_ppu_read: bool,
+    chr_address: u16,
+    memory: Memory,
+    last_data: u8,
+    prev_hpos: i32,
+    ppu_framebuffer: Box<[u32; 256 * 240]>,
+    raw_framebuffer: Box<[u16; 256 * 240]>,
+    output_mode: OutputMode,
+    frame_counter: FrameCounter,
+    palette: Palette,
+    subcarrier_phase: u8,
+    composite: Option<Box<CompositeFrame>>,
+    event_log: Option<Box<PpuEventLog>>,
+    power_on: PowerOnOptions,
+    clock_alignment: u8,
+    reset_hold_half_steps: u32,
+}
+
+impl Lane {
+    fn from_simulation(sim: &SimulationState) -> Self {
+        assert!(
+            sim.hand_off.is_none(),
+            "can't slice a simulation in the middle of a hand-off"
+        );
+        Lane {
+            step_cycle_count: sim.step_cycle_count,
+            prev_ppu_ale: sim.prev_ppu_ale,
+            prev_ppu_write: sim.prev_ppu_write,
+            prev_ppu_read: sim.prev_ppu_read,
+            chr_address: sim.chr_address,
+            memory: sim.memory.clone(),
+            last_data: sim.last_data,
+            prev_hpos: sim.prev_hpos,
+            ppu_framebuffer: sim.ppu_framebuffer.clone(),
+            raw_framebuffer: sim.raw_framebuffer.clone(),
+            output_mode: sim.output_mode,
+            frame_counter: sim.frame_counter.clone(),
+            palette: sim.palette.clone(),
+            subcarrier_phase: sim.subcarrier_phase,
+            composite: sim.composite.clone(),
+            event_log: sim.event_log.clone(),
+            power_on: sim.power_on,
+            clock_alignment: sim.clock_alignment,
+            reset_hold_half_steps: sim.reset_hold_half_steps,
+        }
+    }
+
+    fn store(&self, sim: &mut SimulationState) {
+        sim.step_cycle_count = self.step_cycle_count;
+        sim.prev_ppu_ale = self.prev_ppu_ale;
+        sim.prev_ppu_write = self.prev_ppu_write;
+        sim.prev_ppu_read = self.prev_ppu_read;
+        sim.chr_address = self.chr_address;
+        sim.memory = self.memory.clone();
+        sim.last_data = self.last_data;
+        sim.prev_hpos = self.prev_hpos;
+        sim.ppu_framebuffer = self.ppu_framebuffer.clone();
+        sim.raw_framebuffer = self.raw_framebuffer.clone();
+        sim.output_mode = self.output_mode;
+        sim.frame_counter = self.frame_counter.clone();
+        sim.palette = self.palette.clone();
+        sim.subcarrier_phase = self.subcarrier_phase;
+        sim.composite = self.composite.clone();
+        sim.event_log = self.event_log.clone();
+        sim.power_on = self.power_on;
+        sim.clock_alignment = self.clock_alignment;
+        sim.reset_hold_half_steps = self.reset_hold_half_steps;
+    }
+}
+
+/// Advances 64 independent simulations of the same netlist in a single pass.
+///
+/// Every node and transistor holds one bit per lane, so each recalculation evaluates a node for
+/// all lanes at once. When transistor states differ between lanes, a group is walked separately
+/// for each subset of lanes that sees a different set of connections, in the same order the scalar
+/// engine would walk it. Every lane therefore evolves exactly like a `SimulationState` started
+/// from the same point.
+///
+/// Lanes are typically seeded from a running `SimulationState` with `from_simulation`, varied
+/// with `set_lane`, and inspected afterwards by extracting them with `lane`. Each lane keeps its
+/// own event log and power-on settings. Lanes can't be seeded from a simulation that's in the
+/// middle of a `FastForward` hand-off, as their CPU buses don't serve the hand-off's loader.
+#[derive(Clone)]
+pub struct BitSlicedSimulation {
+    netlist: Arc<Netlist>,
+    node_state: Vec<u64>,
+    node_pullup: Vec<u64>,
+    node_pulldown: Vec<u64>,
+    transistor_on: Vec<u64>,
+    has_ground: u64,
+    has_power: u64,
+    /// Nodes of the current group, each paired with the lanes in which it belongs to the group.
+    group: Vec<(u16, u64)>,
+    /// Pending (lanes, next, end) ranges into the netlist's channel lists while walking a group.
+    group_stack: Vec<(u64, u32, u32)>,
+    group_marks: Vec<u32>,
+    /// Lanes in which a node has already been added to the current group. Only meaningful when the
+    /// node's mark equals `group_generation`.
+    group_lanes: Vec<u64>,
+    group_generation: u32,
+    cur_list: Vec<(u16, u64)>,
+    next_list: Vec<(u16, u64)>,
+    /// Lanes in which a node is already queued in `next_list`.
+    queued_lanes: Vec<u64>,
+    lanes: Vec<Lane>,
+}
+
+impl BitSlicedSimulation {
+    /// Create a simulation with every lane set to the state of `sim`.
+    pub fn from_simulation(sim: &SimulationState) -> Self {
+        let node_count = sim.netlist.node_count();
+        let transistor_count = sim.netlist.transistor_count();
+        let mut bit_sliced = BitSlicedSimulation {
+            netlist: Arc::clone(&sim.netlist),
+            node_state: vec![0; node_count],
+            node_pullup: vec![0; node_count],
+            node_pulldown: vec![0; node_count],
+            transistor_on: vec![0; transistor_count],
+            has_ground: 0,
+            has_power: 0,
+            group: Vec::new(),
+            group_stack: Vec::new(),
+            group_marks: vec![0; node_count],
+            group_lanes: vec![0; node_count],
+            group_generation: 0,
+            cur_list: Vec::new(),
+            next_list: Vec::new(),
+            queued_lanes: vec![0; node_count],
+            lanes: vec![Lane::from_simulation(sim); LANES],
+        };
+
+        let lane_masks = |set: bool| if set { ALL_LANES } else { 0 };
+        for i in 0..node_count {
+            bit_sliced.node_state[i] = lane_masks(sim.node_state.get(i));
+            bit_sliced.node_pullup[i] = lane_masks(sim.node_pullup.get(i));
+            bit_sliced.node_pulldown[i] = lane_masks(sim.node_pulldown.get(i));
+        }
+        for i in 0..transistor_count {
+            bit_sliced.transistor_on[i] = lane_masks(sim.transistor_on.get(i));
+        }
+
+        bit_sliced
+    }
+
+    /// Replace the state of a single lane with the state of `sim`.
+    pub fn set_lane(&mut self, lane: usize, sim: &SimulationState) {
+        assert!(lane < LANES, "lane {} out of range", lane);
+        assert_eq!(
+            self.netlist.node_count(),
+            sim.netlist.node_count(),
+            "simulation was built from a different netlist"
+        );
+
+        let bit = 1 << lane;
+        let assign = |mask: &mut u64, set: bool| {
+            if set {
+                *mask |= bit
+            } else {
+                *mask &= !bit
+            }
+        };
+        for i in 0..self.netlist.node_count() {
+            assign(&mut self.node_state[i], sim.node_state.get(i));
+            assign(&mut self.node_pullup[i], sim.node_pullup.get(i));
+            assign(&mut self.node_pulldown[i], sim.node_pulldown.get(i));
+        }
+        for i in 0..self.netlist.transistor_count() {
+            assign(&mut self.transistor_on[i], sim.transistor_on.get(i));
+        }
+
+        self.lanes[lane] = Lane::from_simulation(sim);
+    }
+
+    /// Extract a single lane as an independent `SimulationState`.
+    pub fn lane(&self, lane: usize) -> SimulationState {
+        assert!(lane < LANES, "lane {} out of range", lane);
+        let mut sim = SimulationState::with_netlist(Arc::clone(&self.netlist));
+        for i in 0..self.netlist.node_count() {
+            sim.node_state.assign(i, lane_bit(self.node_state[i], lane));
+            sim.node_pullup
+                .assign(i, lane_bit(self.node_pullup[i], lane));
+            sim.node_pulldown
+                .assign(i, lane_bit(self.node_pulldown[i], lane));
+        }
+        for i in 0..self.netlist.transistor_count() {
+            sim.transistor_on
+                .assign(i, lane_bit(self.transistor_on[i], lane));
+        }
+
+        self.lanes[lane].store(&mut sim);
+        sim
+    }
+
+    /// The lane-parallel equivalent of `SimulationState::half_step`.
+    pub fn half_step(&mut self) {
+        for lane in self.lanes.iter_mut() {
+            lane.frame_counter.begin_half_step();
+            lane.subcarrier_phase = (lane.subcarrier_phase + 1) % SUBCARRIER_PHASES;
+        }
+        let cpu_clk0 = self.node_lanes(NODE_CPU_CLK0);
+        let clk = self.node_lanes(NODE_CLK0);
+        self.drive(NODE_CLK0, ALL_LANES, !clk);
+
+        // Simulate the 74139's logic
+        let decoded = self.node_lanes(NODE_CPU_AB13)
+            & !self.node_lanes(NODE_CPU_AB14)
+            & !self.node_lanes(NODE_CPU_AB15)
+            & self.node_lanes(NODE_CPU_CLK0);
+        let io_ce_low_half_steps = self.netlist.region.io_ce_low_half_steps();
+        let mut io_ce_high = 0;
+        let mut io_ce_low = 0;
+        for (i, lane) in self.lanes.iter_mut().enumerate() {
+            if lane.step_cycle_count > 0 {
+                lane.step_cycle_count -= 1;
+                if lane.step_cycle_count == 0 {
+                    io_ce_high |= 1 << i;
+                }
+            } else if lane_bit(decoded, i) {
+                io_ce_low |= 1 << i;
+                lane.step_cycle_count = io_ce_low_half_steps;
+            }
+        }
+        if io_ce_high | io_ce_low != 0 {
+            self.drive(NODE_IO_CE, io_ce_high | io_ce_low, io_ce_high);
+        }
+
+        self.handle_chr_bus();
+
+        let cpu_clk0_changed = cpu_clk0 ^ self.node_lanes(NODE_CPU_CLK0);
+        if cpu_clk0_changed != 0 {
+            self.handle_cpu_bus(cpu_clk0_changed & cpu_clk0, cpu_clk0_changed & !cpu_clk0);
+        }
+
+        let pclk1 = self.node_lanes(NODE_PCLK1);
+        for_each_lane(pclk1, |i| {
+            let hpos = i32::from(read_lane(&self.node_state, &HPOS_NODES, i)) - 2;
+            let lane = &mut self.lanes[i];
+            if hpos != lane.prev_hpos {
+                let vpos = read_lane(&self.node_state, &VPOS_NODES, i);
+                lane.frame_counter.update(vpos);
+                if (0..256).contains(&hpos) && vpos < VISIBLE_SCANLINES {
+                    let pixel = read_lane(&self.node_state, &PAL_D_OUT_NODES, i)
+                        | ((!read_lane(&self.node_state, &NOT_EMPH_OUT_NODES, i) & 0x07) << 6);
+                    let index = ((vpos << 8) | (hpos as u16)) as usize;
+                    if lane.output_mode.has_argb() {
+                        lane.ppu_framebuffer[index] = lane.palette.argb(pixel);
+                    }
+                    if lane.output_mode.has_raw_pixels() {
+                        lane.raw_framebuffer[index] = pixel;
+                    }
+                    if let Some(composite) = &mut lane.composite {
+                        composite.write_dot(index, pixel, lane.subcarrier_phase);
+                    }
+                }
+                lane.prev_hpos = hpos;
+            }
+        });
+
+        let node_state = &self.node_state;
+        for (i, lane) in self.lanes.iter_mut().enumerate() {
+            if let Some(log) = &mut lane.event_log {
+                log.log_half_step(lane.frame_counter.count(), &lane_nodes(node_state, i));
+            }
+        }
+    }
+
+    fn handle_chr_bus(&mut self) {
+        let ale = self.node_lanes(NODE_ALE);
+        let rd = self.node_lanes(NODE_RD);
+        let wr = self.node_lanes(NODE_WR);
+
+        let mut db_write_lanes = 0;
+        let mut db_float_lanes = 0;
+        let mut db_values = [0_u8; LANES];
+        for (i, lane) in self.lanes.iter_mut().enumerate() {
+            // rising edge of ALE
+            if lane.prev_ppu_ale && lane_bit(ale, i) {
+                lane.chr_address = read_lane(&self.node_state, &AB_NODES, i);
+            }
+
+            // falling edge of /RD - put bits on bus
+            if lane.prev_ppu_read && !lane_bit(rd, i) {
+                db_write_lanes |= 1 << i;
+                db_values[i] = lane.memory.ppu_read(lane.chr_address);
+            }
+
+            // rising edge of /RD - float the data bus
+            if !lane.prev_ppu_read && lane_bit(rd, i) {
+                db_float_lanes |= 1 << i;
+            }
+        }
+
+        if db_write_lanes != 0 {
+            self.write_byte(DB_NODES, db_write_lanes, &db_values);
+            for_each_lane(db_write_lanes, |i| {
+                let addr = self.lanes[i].chr_address;
+                self.log_lane_event(
+                    i,
+                    PpuEventKind::MemoryAccess {
+                        addr,
+                        data: db_values[i],
+                        write: false,
+                    },
+                );
+            });
+        }
+        if db_float_lanes != 0 {
+            self.float_byte(DB_NODES, db_float_lanes);
+        }
+
+        let node_state = &self.node_state;
+        let rd_now = self.node_lanes(NODE_RD);
+        let wr_now = self.node_lanes(NODE_WR);
+        for (i, lane) in self.lanes.iter_mut().enumerate() {
+            let bus_driven = !lane_bit(rd_now, i) || !lane_bit(wr_now, i);
+            if bus_driven {
+                lane.last_data = read_lane(&self.node_state, &DB_NODES, i) as u8;
+            }
+
+            // rising edge of /WR - store data in RAM
+            if !lane.prev_ppu_write && lane_bit(wr, i) {
+                lane.memory.ppu_write(lane.chr_address, lane.last_data);
+                if let Some(log) = &mut lane.event_log {
+                    log.log(
+                        lane.frame_counter.count(),
+                        &lane_nodes(node_state, i),
+                        PpuEventKind::MemoryAccess {
+                            addr: lane.chr_address,
+                            data: lane.last_data,
+                            write: true,
+                        },
+                    );
+                }
+            }
+
+            lane.prev_ppu_ale = lane_bit(ale, i);
+            lane.prev_ppu_read = lane_bit(rd, i);
+            lane.prev_ppu_write = lane_bit(wr, i);
+        }
+    }
+
+    fn handle_cpu_bus(&mut self, falling_lanes: u64, rising_lanes: u64) {
+        let rw = self.node_lanes(NODE_CPU_RW);
+
+        let mut db_write_lanes = 0;
+        let mut db_float_lanes = 0;
+        let mut db_values = [0_u8; LANES];
+        for_each_lane(falling_lanes & rw, |i| {
+            let a = read_lane(&self.node_state, &CPU_AB_NODES, i);
+            let (d, open_bus) = self.lanes[i].memory.cpu_read(a);
+            if open_bus {
+                db_float_lanes |= 1 << i;
+            } else {
+                db_write_lanes |= 1 << i;
+                db_values[i] = d;
+            }
+        });
+
+        if db_write_lanes != 0 {
+            self.write_byte(CPU_DB_NODES, db_write_lanes, &db_values);
+        }
+        if db_float_lanes != 0 {
+            self.float_byte(CPU_DB_NODES, db_float_lanes);
+        }
+
+        for_each_lane(rising_lanes & !rw, |i| {
+            let a = read_lane(&self.node_state, &CPU_AB_NODES, i);
+            let d = read_lane(&self.node_state, &CPU_DB_NODES, i) as u8;
+            self.lanes[i].memory.cpu_write(a, d);
+        });
+    }
+
+    /// Log an event in a lane that's logging them.
+    fn log_lane_event(&mut self, lane: usize, kind: PpuEventKind) {
+        let node_state = &self.node_state;
+        let lane_state = &mut self.lanes[lane];
+        if let Some(log) = &mut lane_state.event_log {
+            log.log(
+                lane_state.frame_counter.count(),
+                &lane_nodes(node_state, lane),
+                kind,
+            );
+        }
+    }
+
+    fn node_lanes(&self, node_number: u16) -> u64 {
+        self.node_state[node_number as usize]
+    }
+
+    /// Pull `node_number` high in `lanes & high` and low in the rest of `lanes`.
+    fn drive(&mut self, node_number: u16, lanes: u64, high: u64) {
+        let n = node_number as usize;
+        self.node_pullup[n] = (self.node_pullup[n] & !lanes) | (high & lanes);
+        self.node_pulldown[n] = (self.node_pulldown[n] & !lanes) | (!high & lanes);
+        self.recalc_node_list(&[(node_number, lanes)]);
+    }
+
+    fn write_byte(&mut self, nodes: [u16; 8], lanes: u64, values: &[u8; LANES]) {
+        let mut recalc_nodes = [(0_u16, lanes); 8];
+        for (bit, node_number) in nodes.iter().enumerate() {
+            let mut high = 0;
+            for_each_lane(lanes, |i| {
+                if values[i] & (1 << bit) != 0 {
+                    high |= 1 << i;
+                }
+            });
+
+            let n = *node_number as usize;
+            self.node_pullup[n] = (self.node_pullup[n] & !lanes) | high;
+            self.node_pulldown[n] = (self.node_pulldown[n] & !lanes) | (lanes & !high);
+            recalc_nodes[bit].0 = *node_number;
+        }
+
+        self.recalc_node_list(&recalc_nodes);
+    }
+
+    fn float_byte(&mut self, nodes: [u16; 8], lanes: u64) {
+        let mut recalc_nodes = [(0_u16, lanes); 8];
+        for (bit, node_number) in nodes.iter().enumerate() {
+            let n = *node_number as usize;
+            self.node_pullup[n] &= !lanes;
+            self.node_pulldown[n] &= !lanes;
+            recalc_nodes[bit].0 = *node_number;
+        }
+
+        self.recalc_node_list(&recalc_nodes);
+    }
+
+    fn recalc_node_list(&mut self, recalc_list: &[(u16, u64)]) {
+        self.cur_list.clear();
+        self.cur_list.extend_from_slice(recalc_list);
+        for iter_count in 0..100 {
+            if iter_count >= 99 {
+                panic!("iter count exceeded");
+            }
+            for i in 0..self.cur_list.len() {
+                let (node_number, lanes) = self.cur_list[i];
+                if node_number != NODE_GND && node_number != NODE_PWR {
+                    self.recalc_node(node_number, lanes);
+                }
+            }
+
+            if self.next_list.is_empty() {
+                return;
+            }
+
+            for (node_number, _) in &self.next_list {
+                self.queued_lanes[*node_number as usize] = 0;
+            }
+            self.cur_list.clear();
+            mem::swap(&mut self.cur_list, &mut self.next_list);
+        }
+    }
+
+    fn recalc_node(&mut self, node_number: u16, lanes: u64) {
+        self.get_node_group(node_number, lanes);
+        let new_state = self.get_node_value(lanes);
+        for (node_number, group_lanes) in &self.group {
+            let n = *node_number as usize;
+            let changed = *group_lanes & (self.node_state[n] ^ new_state);
+            if changed == 0 {
+                continue;
+            }
+
+            self.node_state[n] ^= changed;
+            let rising = changed & new_state;
+            let falling = changed & !new_state;
+            for i in self.netlist.gates(n) {
+                let i = *i as usize;
+                let on = self.transistor_on[i];
+                let turned_on = rising & !on;
+                let turned_off = falling & on;
+                if turned_on | turned_off != 0 {
+                    self.transistor_on[i] = (on | turned_on) & !turned_off;
+                    let transistor = &self.netlist.transistors[i];
+                    push_next_list(
+                        &mut self.next_list,
+                        &mut self.queued_lanes,
+                        transistor.c1,
+                        turned_on | turned_off,
+                    );
+                    if turned_off != 0 {
+                        push_next_list(
+                            &mut self.next_list,
+                            &mut self.queued_lanes,
+                            transistor.c2,
+                            turned_off,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Resolve the value of the current group in each of `lanes`, following the same rules as
+    /// `SimulationState::get_node_value`.
+    fn get_node_value(&self, lanes: u64) -> u64 {
+        let mut has_ground = self.has_ground;
+        let mut has_power = self.has_power;
+        let contended = has_ground & has_power;
+        if contended != 0 {
+            let mut area_resolved = 0;
+            for (node_number, group_lanes) in &self.group {
+                if AREA_RESOLVED_NODES.contains(node_number) {
+                    area_resolved |= group_lanes;
+                }
+            }
+            has_ground &= !(contended & area_resolved);
+            has_power &= !(contended & area_resolved);
+        }
+
+        let mut value = has_power & !has_ground;
+        let mut undecided = lanes & !has_ground & !has_power;
+
+        // The first pulled node in walk order decides the value.
+        for (node_number, group_lanes) in &self.group {
+            if undecided == 0 {
+                return value;
+            }
+            let n = *node_number as usize;
+            let candidates = group_lanes & undecided;
+            let pulled_up = candidates & self.node_pullup[n];
+            let pulled_down = candidates & !self.node_pullup[n] & self.node_pulldown[n];
+            value |= pulled_up;
+            undecided &= !(pulled_up | pulled_down);
+        }
+
+        if undecided != 0 {
+            let mut hi_area = [0_u64; LANES];
+            let mut lo_area = [0_u64; LANES];
+            for (node_number, group_lanes) in &self.group {
+                let n = *node_number as usize;
+                let area = self.netlist.node_areas[n];
+                for_each_lane(group_lanes & undecided & self.node_state[n], |i| {
+                    hi_area[i] += area
+                });
+                for_each_lane(group_lanes & undecided & !self.node_state[n], |i| {
+                    lo_area[i] += area
+                });
+            }
+            for_each_lane(undecided, |i| {
+                if hi_area[i] > lo_area[i] {
+                    value |= 1 << i;
+                }
+            });
+        }
+
+        value
+    }
+
+    /// Collect, for each of `lanes`, every node connected to `node_number` through transistors
+    /// that are on in that lane.
+    ///
+    /// The walk branches only where lanes disagree about a transistor, and within each lane it
+    /// visits nodes in the same depth-first order as `SimulationState::get_node_group`.
+    fn get_node_group(&mut self, node_number: u16, lanes: u64) {
+        self.has_ground = 0;
+        self.has_power = 0;
+        self.group.clear();
+
+        self.group_generation = self.group_generation.wrapping_add(1);
+        if self.group_generation == 0 {
+            self.group_marks.iter_mut().for_each(|mark| *mark = 0);
+            self.group_generation = 1;
+        }
+        let generation = self.group_generation;
+
+        self.group_marks[node_number as usize] = generation;
+        self.group_lanes[node_number as usize] = lanes;
+        self.group.push((node_number, lanes));
+        self.group_stack.clear();
+        let (next, end) = self.netlist.channel_range(node_number as usize);
+        self.group_stack.push((lanes, next, end));
+
+        while let Some(frame) = self.group_stack.last_mut() {
+            let (frame_lanes, next, end) = *frame;
+            if next == end {
+                self.group_stack.pop();
+                continue;
+            }
+            frame.1 += 1;
+
+            let channel = next as usize;
+            let reached = frame_lanes
+                & self.transistor_on[self.netlist.channel_transistors[channel] as usize];
+            if reached == 0 {
+                continue;
+            }
+
+            let neighbor = self.netlist.channel_neighbors[channel];
+            if neighbor == NODE_GND {
+                self.has_ground |= reached;
+            } else if neighbor == NODE_PWR {
+                self.has_power |= reached;
+            } else {
+                let n = neighbor as usize;
+                if self.group_marks[n] != generation {
+                    self.group_marks[n] = generation;
+                    self.group_lanes[n] = 0;
+                }
+
+                let added = reached & !self.group_lanes[n];
+                if added != 0 {
+                    self.group_lanes[n] |= added;
+                    self.group.push((neighbor, added));
+                    let (next, end) = self.netlist.channel_range(n);
+                    self.group_stack.push((added, next, end));
+                }
+            }
+        }
+    }
+}
+
+fn push_next_list(
+    next_list: &mut Vec<(u16, u64)>,
+    queued_lanes: &mut [u64],
+    node_number: u16,
+    lanes: u64,
+) {
+    if node_number == NODE_GND || node_number == NODE_PWR {
+        return;
+    }
+
+    let queued = &mut queued_lanes[node_number as usize];
+    let added = lanes & !*queued;
+    if added != 0 {
+        *queued |= added;
+        next_list.push((node_number, added));
+    }
+}
+
+#[inline]
+fn lane_bit(mask: u64, lane: usize) -> bool {
+    mask & (1 << lane) != 0
+}
+
+#[inline]
+/// Whether a node is high in one lane.
+fn lane_nodes(node_state: &[u64], lane: usize) -> impl Fn(u16) -> bool + '_ {
+    move |node_number| lane_bit(node_state[node_number as usize], lane)
+}
+
+fn for_each_lane<F: FnMut(usize)>(mut lanes: u64, mut f: F) {
+    while lanes != 0 {
+        f(lanes.trailing_zeros() as usize);
+        lanes &= lanes - 1;
+    }
+}
+
+/// Read the value formed by `nodes` (least significant bit first) in a single lane.
+fn read_lane(node_state: &[u64], nodes: &[u16], lane: usize) -> u16 {
+    let mut res = 0_u16;
+    for (i, node_number) in nodes.iter().enumerate() {
+        res |= (lane_bit(node_state[*node_number as usize], lane) as u16) << i;
+    }
+    res
+}
@@ -0,0 +1,218 @@
+use crate::{
+    consts::{NODE_GND, NODE_PWR},
+    netlist::Netlist,
+};
+
+/// Marks a local channel leading to ground.
+pub const LOCAL_GND: u16 = u16::MAX;
+/// Marks a local channel leading to power.
+pub const LOCAL_PWR: u16 = u16::MAX - 1;
+
+/// The static channel-connected components of a netlist.
+///
+/// Two nodes belong to the same component when some transistor's channel connects them, directly
+/// or through other nodes, ignoring power and ground. Whatever the transistor states are, the group
+/// of a node never extends past its component, so groups can be found by walking the component
+/// alone.
+///
+/// Nodes are numbered within their component by their position in the component's node list,
+/// which is in ascending order. Each node's channels are kept in local numbering as well, in the
+/// same order as the netlist's channel lists.
+#[derive(Clone, Default)]
+pub struct ChannelComponents {
+    component_of: Vec<u32>,
+    local_index: Vec<u16>,
+    /// The nodes of component `c` are `nodes[node_offsets[c]..node_offsets[c + 1]]`.
+    node_offsets: Vec<u32>,
+    nodes: Vec<u16>,
+    /// The channels of the node at position `i` of `nodes` are
+    /// `channels[channel_offsets[i]..channel_offsets[i + 1]]`.
+    channel_offsets: Vec<u32>,
+    /// A transistor and the local index of the node on the other side of its channel, `LOCAL_GND`
+    /// or `LOCAL_PWR`.
+    channels: Vec<(u16, u16)>,
+    largest_len: usize,
+}
+
+impl ChannelComponents {
+    pub fn new(netlist: &Netlist) -> Self {
+        let node_count = netlist.node_count();
+        let mut parents = (0..node_count as u32).collect::<Vec<u32>>();
+
+        for transistor in &netlist.transistors {
+            let (c1, c2) = (transistor.c1, transistor.c2);
+            if is_supply(c1) || is_supply(c2) {
+                continue;
+            }
+
+            let root1 = find_root(&mut parents, u32::from(c1));
+            let root2 = find_root(&mut parents, u32::from(c2));
+            if root1 != root2 {
+                // Keep the lowest node as the root so components come out ordered by it.
+                let (low, high) = (root1.min(root2), root1.max(root2));
+                parents[high as usize] = low;
+            }
+        }
+
+        let mut component_of = vec![0; node_count];
+        let mut local_index = vec![0; node_count];
+        let mut components: Vec<Vec<u16>> = Vec::new();
+        let mut component_by_root = vec![u32::MAX; node_count];
+        for node_number in 0..node_count {
+            let root = find_root(&mut parents, node_number as u32) as usize;
+            if component_by_root[root] == u32::MAX {
+                component_by_root[root] = components.len() as u32;
+                components.push(Vec::new());
+            }
+
+            let component = component_by_root[root];
+            let nodes = &mut components[component as usize];
+            component_of[node_number] = component;
+            local_index[node_number] = nodes.len() as u16;
+            nodes.push(node_number as u16);
+        }
+
+        let mut node_offsets = Vec::with_capacity(components.len() + 1);
+        let mut nodes = Vec::with_capacity(node_count);
+        let mut channel_offsets = Vec::with_capacity(node_count + 1);
+        let mut channels = Vec::new();
+        for component in &components {
+            node_offsets.push(nodes.len() as u32);
+            for node_number in component {
+                nodes.push(*node_number);
+                channel_offsets.push(channels.len() as u32);
+                let (start, end) = netlist.channel_range(*node_number as usize);
+                for channel in start as usize..end as usize {
+                    let neighbor = match netlist.channel_neighbors[channel] {
+                        NODE_GND => LOCAL_GND,
+                        NODE_PWR => LOCAL_PWR,
+                        neighbor => local_index[neighbor as usize],
+                    };
+                    channels.push((netlist.channel_transistors[channel], neighbor));
+                }
+            }
+        }
+        node_offsets.push(nodes.len() as u32);
+        channel_offsets.push(channels.len() as u32);
+
+        ChannelComponents {
+            component_of,
+            local_index,
+            node_offsets,
+            nodes,
+            channel_offsets,
+            channels,
+            largest_len: components.iter().map(Vec::len).max().unwrap_or(0),
+        }
+    }
+
+    /// The number of components.
+    pub fn len(&self) -> usize {
+        self.node_offsets.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of nodes in the largest component.
+    pub fn largest_len(&self) -> usize {
+        self.largest_len
+    }
+
+    /// The component `node_number` belongs to.
+    #[inline]
+    pub fn component_of(&self, node_number: u16) -> usize {
+        self.component_of[node_number as usize] as usize
+    }
+
+    /// The position of `node_number` within its component's node list.
+    #[inline]
+    pub fn local_index(&self, node_number: u16) -> usize {
+        self.local_index[node_number as usize] as usize
+    }
+
+    /// The nodes of `component` in ascending order.
+    #[inline]
+    pub fn nodes(&self, component: usize) -> &[u16] {
+        let start = self.node_offsets[component] as usize;
+        let end = self.node_offsets[component + 1] as usize;
+        &self.nodes[start..end]
+    }
+
+    /// The channels of the node at `local_index` in `component`, as pairs of a transistor index and
+    /// the local index of the node on the other side, `LOCAL_GND` or `LOCAL_PWR`.
+    #[inline]
+    pub fn channels(&self, component: usize, local_index: usize) -> &[(u16, u16)] {
+        let (start, end) = self.channel_range(component, local_index);
+        &self.channels[start as usize..end as usize]
+    }
+
+    #[inline]
+    pub(crate) fn channel_range(&self, component: usize, local_index: usize) -> (u32, u32) {
+        let position = self.node_offsets[component] as usize + local_index;
+        (
+            self.channel_offsets[position],
+            self.channel_offsets[position + 1],
+        )
+    }
+
+    #[inline]
+    pub(crate) fn channel(&self, index: u32) -> (u16, u16) {
+        self.channels[index as usize]
+    }
+
+    /// Iterate over the node lists of every component, in order of their lowest node.
+    pub fn iter(&self) -> impl Iterator<Item = &[u16]> {
+        (0..self.len()).map(move |component| self.nodes(component))
+    }
+}
+
+fn is_supply(node_number: u16) -> bool {
+    node_number == NODE_GND || node_number == NODE_PWR
+}
+
+fn find_root(parents: &mut [u32], mut node: u32) -> u32 {
+    while parents[node as usize] != node {
+        let grandparent = parents[parents[node as usize] as usize];
+        parents[node as usize] = grandparent;
+        node = grandparent;
+    }
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transistors_never_connect_different_components() {
+        let netlist = Netlist::load();
+        let components = &netlist.components;
+        for transistor in &netlist.transistors {
+            if !is_supply(transistor.c1) && !is_supply(transistor.c2) {
+                assert_eq!(
+                    components.component_of(transistor.c1),
+                    components.component_of(transistor.c2)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_node_is_in_exactly_one_component() {
+        let netlist = Netlist::load();
+        let components = &netlist.components;
+        let mut seen = vec![false; netlist.node_count()];
+        for (component, nodes) in components.iter().enumerate() {
+            assert!(nodes.windows(2).all(|pair| pair[0] < pair[1]));
+            for (local_index, node_number) in nodes.iter().enumerate() {
+                assert!(!seen[*node_number as usize]);
+                seen[*node_number as usize] = true;
+                assert_eq!(component, components.component_of(*node_number));
+                assert_eq!(local_index, components.local_index(*node_number));
+            }
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+}
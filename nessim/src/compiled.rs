@@ -0,0 +1,53 @@
+//! Group resolution over channel tables compiled into the binary.
+//!
+//! The build script runs the netlist through the preprocessor and writes out every node's channels
+//! as constant tables, with the node on the other side of each channel by number and each node's
+//! index within its static channel-connected component alongside its range of channels. Groups are
+//! walked over those tables directly, so finding a node's channels is a single lookup rather than
+//! one through its component, and the walk tracks visited nodes in a bit set on the stack sized for
+//! the largest component, from single nodes up to the buses that span most of a chip.
+//!
+//! The walk visits nodes in the same order as `NodeGroup::collect`, so the results are identical to
+//! the interpreter's.
+//!
+//! The tables are generated for the NTSC netlist of both chips. Netlists of a single chip or of
+//! another region number their nodes and transistors differently, so they always use the
+//! interpreter.
+
+use crate::{
+    consts::{NODE_GND, NODE_PWR},
+    netlist::{Chips, Region},
+    SimulationState,
+};
+
+include!(concat!(env!("OUT_DIR"), "/compiled_netlist.rs"));
+
+impl SimulationState {
+    /// Enable or disable the compiled tables. They're enabled by default, and when disabled,
+    /// groups are found by the interpreter as they are without the `compiled` feature.
+    pub fn set_compiled_tables(&mut self, enabled: bool) {
+        self.compiled_tables = enabled;
+    }
+
+    pub(crate) fn resolve_group(&mut self, node_number: u16) -> bool {
+        if !self.compiled_tables
+            || self.netlist.chips != Chips::Both
+            || self.netlist.region != Region::Ntsc
+        {
+            return self.interpret_group(node_number);
+        }
+
+        self.group.collect_from_tables::<VISITED_WORDS>(
+            &self.transistor_on,
+            node_number,
+            &NODE_CHANNELS,
+            &CHANNELS,
+        );
+        self.group.value(
+            &self.netlist,
+            &self.node_state,
+            &self.node_pullup,
+            &self.node_pulldown,
+        )
+    }
+}
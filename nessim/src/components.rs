@@ -1,5 +1,4 @@
 use crate::consts::EMPTYNODE;
-use std::cell::Cell;
 
 #[derive(Clone)]
 pub struct NodeDefinition {
@@ -24,29 +23,6 @@ impl Default for NodeDefinition {
     }
 }
 
-#[derive(Clone)]
-pub struct Node {
-    pub state: Cell<bool>,
-    pub pullup: Cell<bool>,
-    pub pulldown: Cell<bool>,
-    pub floating: Cell<bool>,
-    pub area: u64,
-    pub gates: Vec<u16>,
-}
-
-impl Node {
-    pub fn new(def: NodeDefinition) -> Self {
-        Node {
-            state: Cell::new(def.state),
-            pullup: Cell::new(def.pullup),
-            pulldown: Cell::new(false),
-            floating: Cell::new(true),
-            area: def.area,
-            gates: def.gates,
-        }
-    }
-}
-
 #[derive(Clone)]
 pub struct TransistorDefinition {
     pub name: String,
@@ -55,8 +31,8 @@ pub struct TransistorDefinition {
     pub c2: u16,
 }
 
+#[derive(Clone)]
 pub struct Transistor {
-    pub on: Cell<bool>,
     pub c1: u16,
     pub c2: u16,
 }
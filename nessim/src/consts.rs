@@ -1,10 +1,16 @@
 pub const SPRITE_RAM_SIZE: usize = 0x120;
 pub const PALETTE_RAM_SIZE: usize = 0x20;
+/// How long `io_ce` is held low for each access to a PPU register on an NTSC console.
+pub const IO_CE_LOW_HALF_STEPS: u8 = 11;
+/// How long reset is held low after power-on or a press of the reset button.
+pub const DEFAULT_RESET_HOLD_HALF_STEPS: u32 = 12 * 8 * 2;
 pub const NUM_NODES: usize = 33001;
 pub const EMPTYNODE: u16 = 65535;
 pub const NODE_GND: u16 = 2;
 pub const NODE_PWR: u16 = 1;
 pub const NODE_CLK0: u16 = 772;
+/// `_clk0`, the PPU's inverted copy of `clk0`.
+pub const NODE_NOT_CLK0: u16 = 626;
 pub const NODE_RESET: u16 = 1934;
 pub const NODE_IO_CE: u16 = 5;
 pub const NODE_INT: u16 = 1031;
@@ -15,6 +21,7 @@ pub const NODE_CPU_SO: u16 = 24246;
 pub const NODE_CPU_IRQ: u16 = 23488;
 pub const NODE_CPU_NMI: u16 = 1031;
 pub const NODE_CPU_CLK0: u16 = 24235;
+pub const NODE_CPU_SYNC: u16 = 24547;
 pub const NODE_AB0: u16 = 1991;
 pub const NODE_AB1: u16 = 2370;
 pub const NODE_AB2: u16 = 2650;
@@ -68,6 +75,11 @@ pub const NODE_PAL_D2_OUT: u16 = 6566;
 pub const NODE_PAL_D3_OUT: u16 = 6567;
 pub const NODE_PAL_D4_OUT: u16 = 6564;
 pub const NODE_PAL_D5_OUT: u16 = 6568;
+/// `/emph0_out` to `/emph2_out`, the inverted emphasis outputs driving the video DAC alongside
+/// `pal_d0_out` to `pal_d5_out`.
+pub const NODE_NOT_EMPH0_OUT: u16 = 1184;
+pub const NODE_NOT_EMPH1_OUT: u16 = 1168;
+pub const NODE_NOT_EMPH2_OUT: u16 = 1128;
 pub const NODE_PCLK1: u16 = 58;
 pub const NODE_HPOS0: u16 = 209;
 pub const NODE_HPOS1: u16 = 260;
@@ -88,6 +100,65 @@ pub const NODE_VPOS6: u16 = 543;
 pub const NODE_VPOS7: u16 = 588;
 pub const NODE_VPOS8: u16 = 632;
 
+/// Groups containing any of these nodes are resolved by area even when they connect to both power
+/// and ground.
+pub const AREA_RESOLVED_NODES: [u16; 8] = [359, 566, 691, 871, 870, 864, 856, 818];
+
+pub const DB_NODES: [u16; 8] = [
+    NODE_DB0, NODE_DB1, NODE_DB2, NODE_DB3, NODE_DB4, NODE_DB5, NODE_DB6, NODE_DB7,
+];
+pub const AB_NODES: [u16; 14] = [
+    NODE_AB0, NODE_AB1, NODE_AB2, NODE_AB3, NODE_AB4, NODE_AB5, NODE_AB6, NODE_AB7, NODE_AB8,
+    NODE_AB9, NODE_AB10, NODE_AB11, NODE_AB12, NODE_AB13,
+];
+pub const CPU_DB_NODES: [u16; 8] = [
+    NODE_CPU_DB0,
+    NODE_CPU_DB1,
+    NODE_CPU_DB2,
+    NODE_CPU_DB3,
+    NODE_CPU_DB4,
+    NODE_CPU_DB5,
+    NODE_CPU_DB6,
+    NODE_CPU_DB7,
+];
+pub const CPU_AB_NODES: [u16; 16] = [
+    NODE_CPU_AB0,
+    NODE_CPU_AB1,
+    NODE_CPU_AB2,
+    NODE_CPU_AB3,
+    NODE_CPU_AB4,
+    NODE_CPU_AB5,
+    NODE_CPU_AB6,
+    NODE_CPU_AB7,
+    NODE_CPU_AB8,
+    NODE_CPU_AB9,
+    NODE_CPU_AB10,
+    NODE_CPU_AB11,
+    NODE_CPU_AB12,
+    NODE_CPU_AB13,
+    NODE_CPU_AB14,
+    NODE_CPU_AB15,
+];
+pub const HPOS_NODES: [u16; 9] = [
+    NODE_HPOS0, NODE_HPOS1, NODE_HPOS2, NODE_HPOS3, NODE_HPOS4, NODE_HPOS5, NODE_HPOS6, NODE_HPOS7,
+    NODE_HPOS8,
+];
+pub const VPOS_NODES: [u16; 9] = [
+    NODE_VPOS0, NODE_VPOS1, NODE_VPOS2, NODE_VPOS3, NODE_VPOS4, NODE_VPOS5, NODE_VPOS6, NODE_VPOS7,
+    NODE_VPOS8,
+];
+pub const PAL_D_OUT_NODES: [u16; 6] = [
+    NODE_PAL_D0_OUT,
+    NODE_PAL_D1_OUT,
+    NODE_PAL_D2_OUT,
+    NODE_PAL_D3_OUT,
+    NODE_PAL_D4_OUT,
+    NODE_PAL_D5_OUT,
+];
+/// The emphasis bits of the pixel being output, inverted: red, green and blue on the 2C02.
+pub const NOT_EMPH_OUT_NODES: [u16; 3] =
+    [NODE_NOT_EMPH0_OUT, NODE_NOT_EMPH1_OUT, NODE_NOT_EMPH2_OUT];
+
 #[allow(clippy::unreadable_literal)]
 pub const PALETTE_ARGB: [u32; 64] = [
     0xFF666666, 0xFF002A88, 0xFF1412A7, 0xFF3B00A4, 0xFF5C007E, 0xFF6E0040, 0xFF6C0600, 0xFF561D00,
@@ -0,0 +1,107 @@
+use crate::{
+    consts::{NODE_CLK0, NODE_CPU_CLK0, NODE_CPU_RW, NODE_CPU_SYNC},
+    netlist::{Chips, Netlist},
+    SimulationState,
+};
+use std::sync::Arc;
+
+/// The 6502 simulated without the 2C02, attached to 64 KiB of RAM.
+///
+/// The CPU netlist is the 2A03's, so `clk0` is divided by 12 as on the console and each CPU cycle
+/// takes 24 half-steps. Reads and writes go through the same handling as the combined simulation,
+/// with every address mapped to the RAM.
+///
+/// A program is trapped when it fetches an instruction from the same address twice in a row, which
+/// is how functional test programs such as Klaus Dormann's report success or failure. The 2A03 has
+/// no decimal mode, so that test only reaches its success trap when it's built with
+/// `disable_decimal = 1`.
+#[derive(Clone)]
+pub struct CpuSimulation {
+    sim: SimulationState,
+    cycles: u64,
+    last_fetch: Option<u16>,
+    trap: Option<u16>,
+}
+
+impl CpuSimulation {
+    /// Power on with `image` loaded from `$0000`. The CPU starts at the address in the reset vector
+    /// at `$FFFC`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `image` is larger than 64 KiB.
+    pub fn new(image: &[u8]) -> Self {
+        assert!(image.len() <= 0x10000, "the image doesn't fit in 64 KiB");
+
+        let mut sim = SimulationState::with_netlist(Arc::new(Netlist::load_chips(Chips::Cpu)));
+        sim.memory.flat_cpu_ram = Some(Box::new([0; 0x10000]));
+        sim.init(false);
+        // The CPU doesn't leave reset until the clock runs, so the vector hasn't been read yet.
+        if let Some(ram) = &mut sim.memory.flat_cpu_ram {
+            ram[..image.len()].copy_from_slice(image);
+        }
+
+        CpuSimulation {
+            sim,
+            cycles: 0,
+            last_fetch: None,
+            trap: None,
+        }
+    }
+
+    pub fn half_step(&mut self) {
+        let cpu_clk0 = self.sim.is_node_high(NODE_CPU_CLK0);
+        if self.sim.is_node_high(NODE_CLK0) {
+            self.sim.set_low(NODE_CLK0);
+        } else {
+            self.sim.set_high(NODE_CLK0);
+        }
+
+        if cpu_clk0 != self.sim.is_node_high(NODE_CPU_CLK0) {
+            if cpu_clk0 {
+                if self.sim.is_node_high(NODE_CPU_SYNC) && self.sim.is_node_high(NODE_CPU_RW) {
+                    self.record_fetch();
+                }
+                self.sim.handle_cpu_bus_read();
+            } else {
+                self.sim.handle_cpu_bus_write();
+                self.cycles += 1;
+            }
+        }
+    }
+
+    /// Run until the program is trapped or `max_cycles` CPU cycles have passed since power-on,
+    /// returning the address of the trap.
+    pub fn run_until_trap(&mut self, max_cycles: u64) -> Option<u16> {
+        while self.trap.is_none() && self.cycles < max_cycles {
+            self.half_step();
+        }
+        self.trap
+    }
+
+    /// The address of the instruction the program is trapped at, if it is.
+    pub fn trap(&self) -> Option<u16> {
+        self.trap
+    }
+
+    /// CPU cycles since power-on.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.sim.memory.flat_cpu_ram.as_ref().unwrap()[..]
+    }
+
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.sim.memory.flat_cpu_ram.as_mut().unwrap()[..]
+    }
+
+    fn record_fetch(&mut self) {
+        let addr = self.sim.read_cpu_address_bus();
+        if self.last_fetch == Some(addr) {
+            self.trap = Some(addr);
+        }
+        self.last_fetch = Some(addr);
+    }
+}
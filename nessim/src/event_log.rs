@@ -0,0 +1,262 @@
+use crate::{
+    consts::{
+        CPU_AB_NODES, CPU_DB_NODES, HPOS_NODES, NODE_CPU_RW, NODE_INT, NODE_IO_CE, VPOS_NODES,
+    },
+    SimulationState,
+};
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+/// `vbl_flag`, `spr0_hit` and `spr_overflow`: the flags `$2002` reads.
+const STATUS_FLAG_NODES: [u16; 3] = [5881, 6776, 4064];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PpuEventKind {
+    /// The CPU read or wrote one of the PPU's registers, `$2000` to `$2007`. It's logged when the
+    /// PPU is selected, with the data the bus held when it was deselected.
+    RegisterAccess {
+        addr: u16,
+        data: u8,
+        write: bool,
+    },
+    /// The PPU read or wrote its memory over the CHR bus.
+    MemoryAccess {
+        addr: u16,
+        data: u8,
+        write: bool,
+    },
+    VblankFlag(bool),
+    Sprite0Hit(bool),
+    SpriteOverflow(bool),
+    /// The PPU pulled the CPU's NMI line low, or let it go.
+    Nmi(bool),
+}
+
+/// Something the PPU did, and when.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PpuEvent {
+    /// The number of frames completed before it, as `SimulationState::frame_count` counts them.
+    pub frame: u64,
+    /// The PPU's vertical counter.
+    pub scanline: u16,
+    /// The PPU's horizontal counter.
+    pub dot: u16,
+    pub kind: PpuEventKind,
+}
+
+impl fmt::Display for PpuEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let set_or_cleared = |set: bool| if set { "set" } else { "cleared" };
+        let read_or_write = |write: bool| if write { "write" } else { "read" };
+        write!(
+            f,
+            "frame {} scanline {:3} dot {:3}: ",
+            self.frame, self.scanline, self.dot
+        )?;
+        match self.kind {
+            PpuEventKind::RegisterAccess { addr, data, write } => write!(
+                f,
+                "CPU {} ${:04X} = ${:02X}",
+                read_or_write(write),
+                addr,
+                data
+            ),
+            PpuEventKind::MemoryAccess { addr, data, write } => write!(
+                f,
+                "PPU {} ${:04X} = ${:02X}",
+                read_or_write(write),
+                addr,
+                data
+            ),
+            PpuEventKind::VblankFlag(set) => write!(f, "vblank flag {}", set_or_cleared(set)),
+            PpuEventKind::Sprite0Hit(set) => write!(f, "sprite 0 hit {}", set_or_cleared(set)),
+            PpuEventKind::SpriteOverflow(set) => {
+                write!(f, "sprite overflow {}", set_or_cleared(set))
+            }
+            PpuEventKind::Nmi(asserted) => {
+                write!(f, "NMI {}", if asserted { "asserted" } else { "released" })
+            }
+        }
+    }
+}
+
+/// The `PpuEvent`s of a simulation, in the order they happened.
+#[derive(Clone, Debug)]
+pub struct PpuEventLog {
+    events: Vec<PpuEvent>,
+    /// The status flags and NMI as of the last half-step.
+    prev_signals: [bool; 4],
+    prev_io_ce: bool,
+    /// The index of the register access under way, whose data is filled in until the PPU is
+    /// deselected.
+    pending_access: Option<usize>,
+}
+
+impl PpuEventLog {
+    pub fn events(&self) -> &[PpuEvent] {
+        &self.events
+    }
+
+    /// The events on one scanline of one frame.
+    pub fn on_scanline(&self, frame: u64, scanline: u16) -> impl Iterator<Item = &PpuEvent> {
+        self.events
+            .iter()
+            .filter(move |event| event.frame == frame && event.scanline == scanline)
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.pending_access = None;
+    }
+
+    /// Write the events one per line.
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for event in &self.events {
+            writeln!(writer, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl PpuEventLog {
+    /// Start an empty log of the PPU whose nodes `is_node_high` reads.
+    pub(crate) fn new<F: Fn(u16) -> bool>(is_node_high: &F) -> Self {
+        PpuEventLog {
+            events: Vec::new(),
+            prev_signals: read_event_signals(is_node_high),
+            prev_io_ce: is_node_high(NODE_IO_CE),
+            pending_access: None,
+        }
+    }
+
+    /// Log an event at the PPU's current position, after `frame` frames were completed.
+    pub(crate) fn log<F: Fn(u16) -> bool>(
+        &mut self,
+        frame: u64,
+        is_node_high: &F,
+        kind: PpuEventKind,
+    ) {
+        self.events.push(event_at(frame, is_node_high, kind));
+    }
+
+    /// Log the changes to the signals watched at the end of each half-step.
+    pub(crate) fn log_half_step<F: Fn(u16) -> bool>(&mut self, frame: u64, is_node_high: &F) {
+        let io_ce = is_node_high(NODE_IO_CE);
+        if self.prev_io_ce && !io_ce {
+            let addr = 0x2000 | read_nodes(&CPU_AB_NODES[..3], is_node_high);
+            let write = !is_node_high(NODE_CPU_RW);
+            self.pending_access = Some(self.events.len());
+            self.log(
+                frame,
+                is_node_high,
+                PpuEventKind::RegisterAccess {
+                    addr,
+                    data: 0,
+                    write,
+                },
+            );
+        }
+        if io_ce {
+            self.pending_access = None;
+        } else if let Some(index) = self.pending_access {
+            // The data bus settles while the PPU is selected, so keep the latest value.
+            let data = read_nodes(&CPU_DB_NODES, is_node_high) as u8;
+            if let PpuEventKind::RegisterAccess { data: pending, .. } = &mut self.events[index].kind
+            {
+                *pending = data;
+            }
+        }
+        self.prev_io_ce = io_ce;
+
+        let signals = read_event_signals(is_node_high);
+        let kinds = [
+            PpuEventKind::VblankFlag,
+            PpuEventKind::Sprite0Hit,
+            PpuEventKind::SpriteOverflow,
+            PpuEventKind::Nmi,
+        ];
+        for ((signal, prev), kind) in signals.iter().zip(&self.prev_signals).zip(&kinds) {
+            if signal != prev {
+                self.events
+                    .push(event_at(frame, is_node_high, kind(*signal)));
+            }
+        }
+        self.prev_signals = signals;
+    }
+}
+
+/// The status flags, then whether NMI is asserted.
+fn read_event_signals<F: Fn(u16) -> bool>(is_node_high: &F) -> [bool; 4] {
+    [
+        is_node_high(STATUS_FLAG_NODES[0]),
+        is_node_high(STATUS_FLAG_NODES[1]),
+        is_node_high(STATUS_FLAG_NODES[2]),
+        !is_node_high(NODE_INT),
+    ]
+}
+
+fn read_nodes<F: Fn(u16) -> bool>(nodes: &[u16], is_node_high: &F) -> u16 {
+    let mut res = 0;
+    for (i, node_number) in nodes.iter().enumerate() {
+        res |= u16::from(is_node_high(*node_number)) << i;
+    }
+    res
+}
+
+fn event_at<F: Fn(u16) -> bool>(frame: u64, is_node_high: &F, kind: PpuEventKind) -> PpuEvent {
+    PpuEvent {
+        frame,
+        scanline: read_nodes(&VPOS_NODES, is_node_high),
+        dot: read_nodes(&HPOS_NODES, is_node_high),
+        kind,
+    }
+}
+
+impl SimulationState {
+    /// The events logged since `set_event_logging` turned logging on.
+    pub fn event_log(&self) -> Option<&PpuEventLog> {
+        self.event_log.as_deref()
+    }
+
+    pub fn event_log_mut(&mut self) -> Option<&mut PpuEventLog> {
+        self.event_log.as_deref_mut()
+    }
+
+    /// Turn on or off logging what the PPU does: register accesses, memory fetches, changes to its
+    /// status flags and NMI. Each half-step takes a little longer while it's on.
+    pub fn set_event_logging(&mut self, enabled: bool) {
+        if enabled == self.event_log.is_some() {
+            return;
+        }
+        self.event_log = if enabled {
+            Some(Box::new(PpuEventLog::new(&|node_number| {
+                self.is_node_high(node_number)
+            })))
+        } else {
+            None
+        };
+    }
+
+    pub(crate) fn log_event(&mut self, kind: PpuEventKind) {
+        if let Some(mut log) = self.event_log.take() {
+            log.log(
+                self.frame_count(),
+                &|node_number| self.is_node_high(node_number),
+                kind,
+            );
+            self.event_log = Some(log);
+        }
+    }
+
+    /// Log the changes to the signals watched at the end of each half-step.
+    pub(crate) fn log_half_step_events(&mut self) {
+        if let Some(mut log) = self.event_log.take() {
+            log.log_half_step(self.frame_count(), &|node_number| {
+                self.is_node_high(node_number)
+            });
+            self.event_log = Some(log);
+        }
+    }
+}
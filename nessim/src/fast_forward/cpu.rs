@@ -0,0 +1,802 @@
+/// The memory map seen by the behavioural CPU.
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, val: u8);
+}
+
+pub const FLAG_CARRY: u8 = 0x01;
+pub const FLAG_ZERO: u8 = 0x02;
+pub const FLAG_INTERRUPT: u8 = 0x04;
+pub const FLAG_DECIMAL: u8 = 0x08;
+pub const FLAG_BREAK: u8 = 0x10;
+pub const FLAG_UNUSED: u8 = 0x20;
+pub const FLAG_OVERFLOW: u8 = 0x40;
+pub const FLAG_NEGATIVE: u8 = 0x80;
+
+const NMI_VECTOR: u16 = 0xfffa;
+const RESET_VECTOR: u16 = 0xfffc;
+const IRQ_VECTOR: u16 = 0xfffe;
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+/// An instruction-stepped core of the 2A03's 6502.
+///
+/// The 2A03 has decimal mode cut out, so `SED` sets D but `ADC` and `SBC` stay binary. The
+/// undocumented opcodes do what they do on NMOS parts. `XAA` and `LAX #imm` mix in a constant that
+/// varies from chip to chip and is taken to be `$FF`, and the `JAM` opcodes halt the CPU until it's
+/// powered off.
+#[derive(Clone)]
+pub struct Cpu {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub s: u8,
+    pub p: u8,
+    pub pc: u16,
+    /// CPU cycles executed since power-on.
+    pub cycles: u64,
+    nmi_pending: bool,
+    /// Set by a `JAM` opcode, after which the CPU ignores NMIs and executes nothing.
+    jammed: bool,
+}
+
+impl Cpu {
+    pub fn new() -> Self {
+        Cpu {
+            a: 0,
+            x: 0,
+            y: 0,
+            s: 0xfd,
+            p: FLAG_INTERRUPT | FLAG_UNUSED,
+            pc: 0,
+            cycles: 0,
+            nmi_pending: false,
+            jammed: false,
+        }
+    }
+
+    pub fn reset<B: Bus>(&mut self, bus: &mut B) {
+        self.s = self.s.wrapping_sub(3);
+        self.p |= FLAG_INTERRUPT;
+        self.pc = read_word(bus, RESET_VECTOR);
+        self.cycles += 7;
+    }
+
+    /// Latch a falling edge on the NMI line, to be serviced at the next instruction boundary.
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = !self.jammed;
+    }
+
+    pub fn is_nmi_pending(&self) -> bool {
+        self.nmi_pending
+    }
+
+    /// Service a pending NMI or execute one instruction, returning the cycles taken.
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> u32 {
+        let cycles = if self.jammed {
+            1
+        } else if self.nmi_pending {
+            self.nmi_pending = false;
+            self.interrupt(bus, NMI_VECTOR, false);
+            7
+        } else {
+            self.execute(bus)
+        };
+        self.cycles += u64::from(cycles);
+        cycles
+    }
+
+    fn interrupt<B: Bus>(&mut self, bus: &mut B, vector: u16, brk: bool) {
+        let pc = self.pc;
+        self.push(bus, (pc >> 8) as u8);
+        self.push(bus, pc as u8);
+        let p = if brk {
+            self.p | FLAG_BREAK | FLAG_UNUSED
+        } else {
+            (self.p & !FLAG_BREAK) | FLAG_UNUSED
+        };
+        self.push(bus, p);
+        self.p |= FLAG_INTERRUPT;
+        self.pc = read_word(bus, vector);
+    }
+
+    fn execute<B: Bus>(&mut self, bus: &mut B) -> u32 {
+        let opcode = self.fetch(bus);
+        let (mode, base_cycles) = decode(opcode);
+        let (addr, page_crossed) = self.operand_address(bus, mode);
+        let mut cycles = base_cycles;
+
+        // Reads through indexed modes take an extra cycle when the index crosses a page.
+        let read_penalty = if page_crossed { 1 } else { 0 };
+
+        match opcode {
+            // Loads and stores
+            0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => {
+                self.a = bus.read(addr);
+                self.set_zn(self.a);
+                cycles += read_penalty;
+            }
+            0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => {
+                self.x = bus.read(addr);
+                self.set_zn(self.x);
+                cycles += read_penalty;
+            }
+            0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => {
+                self.y = bus.read(addr);
+                self.set_zn(self.y);
+                cycles += read_penalty;
+            }
+            0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => bus.write(addr, self.a),
+            0x86 | 0x96 | 0x8e => bus.write(addr, self.x),
+            0x84 | 0x94 | 0x8c => bus.write(addr, self.y),
+
+            // Transfers
+            0xaa => {
+                self.x = self.a;
+                self.set_zn(self.x);
+            }
+            0xa8 => {
+                self.y = self.a;
+                self.set_zn(self.y);
+            }
+            0x8a => {
+                self.a = self.x;
+                self.set_zn(self.a);
+            }
+            0x98 => {
+                self.a = self.y;
+                self.set_zn(self.a);
+            }
+            0xba => {
+                self.x = self.s;
+                self.set_zn(self.x);
+            }
+            0x9a => self.s = self.x,
+
+            // Stack
+            0x48 => self.push(bus, self.a),
+            0x08 => self.push(bus, self.p | FLAG_BREAK | FLAG_UNUSED),
+            0x68 => {
+                self.a = self.pull(bus);
+                self.set_zn(self.a);
+            }
+            0x28 => self.p = (self.pull(bus) & !FLAG_BREAK) | FLAG_UNUSED,
+
+            // Arithmetic and logic
+            0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => {
+                let val = bus.read(addr);
+                self.add(val);
+                cycles += read_penalty;
+            }
+            0xe9 | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => {
+                let val = bus.read(addr);
+                self.add(!val);
+                cycles += read_penalty;
+            }
+            0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => {
+                self.a &= bus.read(addr);
+                self.set_zn(self.a);
+                cycles += read_penalty;
+            }
+            0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => {
+                self.a |= bus.read(addr);
+                self.set_zn(self.a);
+                cycles += read_penalty;
+            }
+            0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => {
+                self.a ^= bus.read(addr);
+                self.set_zn(self.a);
+                cycles += read_penalty;
+            }
+            0xc9 | 0xc5 | 0xd5 | 0xcd | 0xdd | 0xd9 | 0xc1 | 0xd1 => {
+                let val = bus.read(addr);
+                self.compare(self.a, val);
+                cycles += read_penalty;
+            }
+            0xe0 | 0xe4 | 0xec => {
+                let val = bus.read(addr);
+                self.compare(self.x, val);
+            }
+            0xc0 | 0xc4 | 0xcc => {
+                let val = bus.read(addr);
+                self.compare(self.y, val);
+            }
+            0x24 | 0x2c => {
+                let val = bus.read(addr);
+                self.set_flag(FLAG_ZERO, self.a & val == 0);
+                self.set_flag(FLAG_OVERFLOW, val & 0x40 != 0);
+                self.set_flag(FLAG_NEGATIVE, val & 0x80 != 0);
+            }
+
+            // Increments and decrements
+            0xe6 | 0xf6 | 0xee | 0xfe => {
+                let val = bus.read(addr).wrapping_add(1);
+                bus.write(addr, val);
+                self.set_zn(val);
+            }
+            0xc6 | 0xd6 | 0xce | 0xde => {
+                let val = bus.read(addr).wrapping_sub(1);
+                bus.write(addr, val);
+                self.set_zn(val);
+            }
+            0xe8 => {
+                self.x = self.x.wrapping_add(1);
+                self.set_zn(self.x);
+            }
+            0xc8 => {
+                self.y = self.y.wrapping_add(1);
+                self.set_zn(self.y);
+            }
+            0xca => {
+                self.x = self.x.wrapping_sub(1);
+                self.set_zn(self.x);
+            }
+            0x88 => {
+                self.y = self.y.wrapping_sub(1);
+                self.set_zn(self.y);
+            }
+
+            // Shifts and rotates
+            0x0a => self.a = self.shift_left(self.a, false),
+            0x4a => self.a = self.shift_right(self.a, false),
+            0x2a => self.a = self.shift_left(self.a, true),
+            0x6a => self.a = self.shift_right(self.a, true),
+            0x06 | 0x16 | 0x0e | 0x1e => {
+                let val = self.shift_left(bus.read(addr), false);
+                bus.write(addr, val);
+            }
+            0x46 | 0x56 | 0x4e | 0x5e => {
+                let val = self.shift_right(bus.read(addr), false);
+                bus.write(addr, val);
+            }
+            0x26 | 0x36 | 0x2e | 0x3e => {
+                let val = self.shift_left(bus.read(addr), true);
+                bus.write(addr, val);
+            }
+            0x66 | 0x76 | 0x6e | 0x7e => {
+                let val = self.shift_right(bus.read(addr), true);
+                bus.write(addr, val);
+            }
+
+            // Jumps and calls
+            0x4c | 0x6c => self.pc = addr,
+            0x20 => {
+                let ret = self.pc.wrapping_sub(1);
+                self.push(bus, (ret >> 8) as u8);
+                self.push(bus, ret as u8);
+                self.pc = addr;
+            }
+            0x60 => {
+                let lo = u16::from(self.pull(bus));
+                let hi = u16::from(self.pull(bus));
+                self.pc = ((hi << 8) | lo).wrapping_add(1);
+            }
+            0x40 => {
+                self.p = (self.pull(bus) & !FLAG_BREAK) | FLAG_UNUSED;
+                let lo = u16::from(self.pull(bus));
+                let hi = u16::from(self.pull(bus));
+                self.pc = (hi << 8) | lo;
+            }
+            0x00 => {
+                self.pc = self.pc.wrapping_add(1);
+                self.interrupt(bus, IRQ_VECTOR, true);
+            }
+
+            // Branches
+            0x10 => cycles += self.branch(addr, self.p & FLAG_NEGATIVE == 0),
+            0x30 => cycles += self.branch(addr, self.p & FLAG_NEGATIVE != 0),
+            0x50 => cycles += self.branch(addr, self.p & FLAG_OVERFLOW == 0),
+            0x70 => cycles += self.branch(addr, self.p & FLAG_OVERFLOW != 0),
+            0x90 => cycles += self.branch(addr, self.p & FLAG_CARRY == 0),
+            0xb0 => cycles += self.branch(addr, self.p & FLAG_CARRY != 0),
+            0xd0 => cycles += self.branch(addr, self.p & FLAG_ZERO == 0),
+            0xf0 => cycles += self.branch(addr, self.p & FLAG_ZERO != 0),
+
+            // Flags
+            0x18 => self.set_flag(FLAG_CARRY, false),
+            0x38 => self.set_flag(FLAG_CARRY, true),
+            0x58 => self.set_flag(FLAG_INTERRUPT, false),
+            0x78 => self.set_flag(FLAG_INTERRUPT, true),
+            0xb8 => self.set_flag(FLAG_OVERFLOW, false),
+            0xd8 => self.set_flag(FLAG_DECIMAL, false),
+            0xf8 => self.set_flag(FLAG_DECIMAL, true),
+
+            // NOPs, including the unofficial ones that read an operand
+            0xea | 0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa => {}
+            0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 | 0x04 | 0x44 | 0x64 | 0x14 | 0x34 | 0x54 | 0x74
+            | 0xd4 | 0xf4 | 0x0c => {
+                bus.read(addr);
+            }
+            0x1c | 0x3c | 0x5c | 0x7c | 0xdc | 0xfc => {
+                bus.read(addr);
+                cycles += read_penalty;
+            }
+
+            // Undocumented read-modify-write opcodes, which follow the shift or increment with
+            // an ALU operation on the result
+            0x03 | 0x07 | 0x0f | 0x13 | 0x17 | 0x1b | 0x1f => {
+                let val = self.shift_left(bus.read(addr), false);
+                bus.write(addr, val);
+                self.a |= val;
+                self.set_zn(self.a);
+            }
+            0x23 | 0x27 | 0x2f | 0x33 | 0x37 | 0x3b | 0x3f => {
+                let val = self.shift_left(bus.read(addr), true);
+                bus.write(addr, val);
+                self.a &= val;
+                self.set_zn(self.a);
+            }
+            0x43 | 0x47 | 0x4f | 0x53 | 0x57 | 0x5b | 0x5f => {
+                let val = self.shift_right(bus.read(addr), false);
+                bus.write(addr, val);
+                self.a ^= val;
+                self.set_zn(self.a);
+            }
+            0x63 | 0x67 | 0x6f | 0x73 | 0x77 | 0x7b | 0x7f => {
+                let val = self.shift_right(bus.read(addr), true);
+                bus.write(addr, val);
+                self.add(val);
+            }
+            0xc3 | 0xc7 | 0xcf | 0xd3 | 0xd7 | 0xdb | 0xdf => {
+                let val = bus.read(addr).wrapping_sub(1);
+                bus.write(addr, val);
+                self.compare(self.a, val);
+            }
+            0xe3 | 0xe7 | 0xef | 0xf3 | 0xf7 | 0xfb | 0xff => {
+                let val = bus.read(addr).wrapping_add(1);
+                bus.write(addr, val);
+                self.add(!val);
+            }
+
+            // Other undocumented loads, stores and ALU operations
+            0x83 | 0x87 | 0x8f | 0x97 => bus.write(addr, self.a & self.x),
+            0xa3 | 0xa7 | 0xaf | 0xb3 | 0xb7 | 0xbf => {
+                self.a = bus.read(addr);
+                self.x = self.a;
+                self.set_zn(self.a);
+                cycles += read_penalty;
+            }
+            0xbb => {
+                self.s &= bus.read(addr);
+                self.a = self.s;
+                self.x = self.s;
+                self.set_zn(self.s);
+                cycles += read_penalty;
+            }
+            0x93 | 0x9f => self.store_high_and(bus, addr, self.y, self.a & self.x),
+            0x9b => {
+                self.s = self.a & self.x;
+                self.store_high_and(bus, addr, self.y, self.s);
+            }
+            0x9c => self.store_high_and(bus, addr, self.x, self.y),
+            0x9e => self.store_high_and(bus, addr, self.y, self.x),
+            0x0b | 0x2b => {
+                self.a &= bus.read(addr);
+                self.set_zn(self.a);
+                self.set_flag(FLAG_CARRY, self.a & 0x80 != 0);
+            }
+            0x4b => {
+                let val = self.a & bus.read(addr);
+                self.a = self.shift_right(val, false);
+            }
+            0x6b => {
+                let val = self.a & bus.read(addr);
+                self.a = self.shift_right(val, true);
+                self.set_flag(FLAG_CARRY, self.a & 0x40 != 0);
+                self.set_flag(FLAG_OVERFLOW, (self.a ^ (self.a << 1)) & 0x40 != 0);
+            }
+            0x8b => {
+                self.a = self.x & bus.read(addr);
+                self.set_zn(self.a);
+            }
+            0xab => {
+                self.a = bus.read(addr);
+                self.x = self.a;
+                self.set_zn(self.a);
+            }
+            0xcb => {
+                let val = bus.read(addr);
+                let and = self.a & self.x;
+                self.set_flag(FLAG_CARRY, and >= val);
+                self.x = and.wrapping_sub(val);
+                self.set_zn(self.x);
+            }
+            0xeb => {
+                let val = bus.read(addr);
+                self.add(!val);
+            }
+
+            // JAM
+            _ => {
+                self.jammed = true;
+                self.pc = self.pc.wrapping_sub(1);
+            }
+        }
+
+        cycles
+    }
+
+    fn operand_address<B: Bus>(&mut self, bus: &mut B, mode: Mode) -> (u16, bool) {
+        match mode {
+            Mode::Implied | Mode::Accumulator => (0, false),
+            Mode::Immediate => {
+                let addr = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                (addr, false)
+            }
+            Mode::ZeroPage => (u16::from(self.fetch(bus)), false),
+            Mode::ZeroPageX => (u16::from(self.fetch(bus).wrapping_add(self.x)), false),
+            Mode::ZeroPageY => (u16::from(self.fetch(bus).wrapping_add(self.y)), false),
+            Mode::Absolute => (self.fetch_word(bus), false),
+            Mode::AbsoluteX => indexed(self.fetch_word(bus), self.x),
+            Mode::AbsoluteY => indexed(self.fetch_word(bus), self.y),
+            Mode::Indirect => {
+                // The pointer's high byte is read from the start of the same page.
+                let pointer = self.fetch_word(bus);
+                let lo = u16::from(bus.read(pointer));
+                let hi = u16::from(bus.read((pointer & 0xff00) | (pointer.wrapping_add(1) & 0xff)));
+                ((hi << 8) | lo, false)
+            }
+            Mode::IndirectX => {
+                let pointer = self.fetch(bus).wrapping_add(self.x);
+                (read_zero_page_word(bus, pointer), false)
+            }
+            Mode::IndirectY => {
+                let pointer = self.fetch(bus);
+                indexed(read_zero_page_word(bus, pointer), self.y)
+            }
+            Mode::Relative => {
+                let offset = self.fetch(bus) as i8;
+                (self.pc.wrapping_add(offset as u16), false)
+            }
+        }
+    }
+
+    fn fetch<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let val = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        val
+    }
+
+    fn fetch_word<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let lo = u16::from(self.fetch(bus));
+        let hi = u16::from(self.fetch(bus));
+        (hi << 8) | lo
+    }
+
+    fn push<B: Bus>(&mut self, bus: &mut B, val: u8) {
+        bus.write(0x100 | u16::from(self.s), val);
+        self.s = self.s.wrapping_sub(1);
+    }
+
+    fn pull<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        self.s = self.s.wrapping_add(1);
+        bus.read(0x100 | u16::from(self.s))
+    }
+
+    fn set_flag(&mut self, flag: u8, set: bool) {
+        if set {
+            self.p |= flag;
+        } else {
+            self.p &= !flag;
+        }
+    }
+
+    fn set_zn(&mut self, val: u8) {
+        self.set_flag(FLAG_ZERO, val == 0);
+        self.set_flag(FLAG_NEGATIVE, val & 0x80 != 0);
+    }
+
+    fn add(&mut self, val: u8) {
+        let sum = u16::from(self.a) + u16::from(val) + u16::from(self.p & FLAG_CARRY);
+        let result = sum as u8;
+        self.set_flag(FLAG_CARRY, sum > 0xff);
+        self.set_flag(
+            FLAG_OVERFLOW,
+            (self.a ^ result) & (val ^ result) & 0x80 != 0,
+        );
+        self.a = result;
+        self.set_zn(result);
+    }
+
+    /// The unstable stores, which write `val` ANDed with one more than the high byte of the
+    /// unindexed address, and write to that value's page instead if the index crosses a page.
+    fn store_high_and<B: Bus>(&mut self, bus: &mut B, addr: u16, index: u8, val: u8) {
+        let base = addr.wrapping_sub(u16::from(index));
+        let val = val & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if addr & 0xff00 != base & 0xff00 {
+            (u16::from(val) << 8) | (addr & 0x00ff)
+        } else {
+            addr
+        };
+        bus.write(addr, val);
+    }
+
+    fn compare(&mut self, reg: u8, val: u8) {
+        self.set_flag(FLAG_CARRY, reg >= val);
+        self.set_zn(reg.wrapping_sub(val));
+    }
+
+    fn shift_left(&mut self, val: u8, rotate: bool) -> u8 {
+        let carry_in = if rotate { self.p & FLAG_CARRY } else { 0 };
+        self.set_flag(FLAG_CARRY, val & 0x80 != 0);
+        let result = (val << 1) | carry_in;
+        self.set_zn(result);
+        result
+    }
+
+    fn shift_right(&mut self, val: u8, rotate: bool) -> u8 {
+        let carry_in = if rotate && self.p & FLAG_CARRY != 0 {
+            0x80
+        } else {
+            0
+        };
+        self.set_flag(FLAG_CARRY, val & 1 != 0);
+        let result = (val >> 1) | carry_in;
+        self.set_zn(result);
+        result
+    }
+
+    /// Take a branch if `condition` holds, returning the extra cycles it costs.
+    fn branch(&mut self, target: u16, condition: bool) -> u32 {
+        if !condition {
+            return 0;
+        }
+
+        let extra = if target & 0xff00 != self.pc & 0xff00 {
+            2
+        } else {
+            1
+        };
+        self.pc = target;
+        extra
+    }
+}
+
+fn indexed(base: u16, index: u8) -> (u16, bool) {
+    let addr = base.wrapping_add(u16::from(index));
+    (addr, addr & 0xff00 != base & 0xff00)
+}
+
+fn read_word<B: Bus>(bus: &mut B, addr: u16) -> u16 {
+    let lo = u16::from(bus.read(addr));
+    let hi = u16::from(bus.read(addr.wrapping_add(1)));
+    (hi << 8) | lo
+}
+
+fn read_zero_page_word<B: Bus>(bus: &mut B, pointer: u8) -> u16 {
+    let lo = u16::from(bus.read(u16::from(pointer)));
+    let hi = u16::from(bus.read(u16::from(pointer.wrapping_add(1))));
+    (hi << 8) | lo
+}
+
+/// The addressing mode and base cycle count of an opcode.
+fn decode(opcode: u8) -> (Mode, u32) {
+    use self::Mode::*;
+    match opcode {
+        0x00 => (Implied, 7),
+        0x20 => (Absolute, 6),
+        0x40 | 0x60 => (Implied, 6),
+        0x4c => (Absolute, 3),
+        0x6c => (Indirect, 5),
+        0x10 | 0x30 | 0x50 | 0x70 | 0x90 | 0xb0 | 0xd0 | 0xf0 => (Relative, 2),
+        0x08 | 0x48 => (Implied, 3),
+        0x28 | 0x68 => (Implied, 4),
+        0x0a | 0x2a | 0x4a | 0x6a => (Accumulator, 2),
+        0x06 | 0x26 | 0x46 | 0x66 | 0xc6 | 0xe6 => (ZeroPage, 5),
+        0x16 | 0x36 | 0x56 | 0x76 | 0xd6 | 0xf6 => (ZeroPageX, 6),
+        0x0e | 0x2e | 0x4e | 0x6e | 0xce | 0xee => (Absolute, 6),
+        0x1e | 0x3e | 0x5e | 0x7e | 0xde | 0xfe => (AbsoluteX, 7),
+        0x96 | 0xb6 => (ZeroPageY, 4),
+        0xbe => (AbsoluteY, 4),
+        0x9d => (AbsoluteX, 5),
+        0x99 => (AbsoluteY, 5),
+        0x91 | 0x93 => (IndirectY, 6),
+        0x9c => (AbsoluteX, 5),
+        0x9b | 0x9e | 0x9f => (AbsoluteY, 5),
+        0x83 | 0xa3 => (IndirectX, 6),
+        0x87 | 0xa7 => (ZeroPage, 3),
+        0x8f | 0xaf => (Absolute, 4),
+        0x97 | 0xb7 => (ZeroPageY, 4),
+        0xb3 => (IndirectY, 5),
+        0xbb | 0xbf => (AbsoluteY, 4),
+        0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 | 0xa0 | 0xa2 | 0xc0 | 0xe0 => (Immediate, 2),
+        0x04 | 0x44 | 0x64 | 0x84 | 0x86 | 0xa4 | 0xa6 | 0x24 | 0xc4 | 0xe4 => (ZeroPage, 3),
+        0x14 | 0x34 | 0x54 | 0x74 | 0xd4 | 0xf4 | 0x94 | 0xb4 => (ZeroPageX, 4),
+        0x0c | 0x2c | 0x8c | 0x8e | 0xac | 0xae | 0xcc | 0xec => (Absolute, 4),
+        0x1c | 0x3c | 0x5c | 0x7c | 0xdc | 0xfc | 0xbc => (AbsoluteX, 4),
+        // The remaining opcodes follow the regular layout of the ALU group.
+        _ if opcode & 0x03 == 0x01 => match (opcode >> 2) & 0x07 {
+            0 => (IndirectX, 6),
+            1 => (ZeroPage, 3),
+            2 => (Immediate, 2),
+            3 => (Absolute, 4),
+            4 => (IndirectY, 5),
+            5 => (ZeroPageX, 4),
+            6 => (AbsoluteY, 4),
+            _ => (AbsoluteX, 4),
+        },
+        // As do the undocumented read-modify-write opcodes beside it.
+        _ if opcode & 0x03 == 0x03 => match (opcode >> 2) & 0x07 {
+            0 => (IndirectX, 8),
+            1 => (ZeroPage, 5),
+            2 => (Immediate, 2),
+            3 => (Absolute, 6),
+            4 => (IndirectY, 8),
+            5 => (ZeroPageX, 6),
+            6 => (AbsoluteY, 7),
+            _ => (AbsoluteX, 7),
+        },
+        _ => (Implied, 2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FlatBus {
+        ram: Vec<u8>,
+    }
+
+    impl Bus for FlatBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.ram[addr as usize]
+        }
+
+        fn write(&mut self, addr: u16, val: u8) {
+            self.ram[addr as usize] = val;
+        }
+    }
+
+    fn run(program: &[u8], steps: usize) -> (Cpu, FlatBus, u64) {
+        let mut bus = FlatBus {
+            ram: vec![0; 0x10000],
+        };
+        bus.ram[0x8000..0x8000 + program.len()].copy_from_slice(program);
+        bus.ram[0xfffc] = 0x00;
+        bus.ram[0xfffd] = 0x80;
+
+        let mut cpu = Cpu::new();
+        cpu.reset(&mut bus);
+        let start = cpu.cycles;
+        for _ in 0..steps {
+            cpu.step(&mut bus);
+        }
+        let cycles = cpu.cycles - start;
+        (cpu, bus, cycles)
+    }
+
+    #[test]
+    fn adc_sets_carry_and_overflow() {
+        // CLC; LDA #$7F; ADC #$01
+        let (cpu, _, cycles) = run(&[0x18, 0xa9, 0x7f, 0x69, 0x01], 3);
+        assert_eq!(0x80, cpu.a);
+        assert!(cpu.p & FLAG_OVERFLOW != 0);
+        assert!(cpu.p & FLAG_NEGATIVE != 0);
+        assert!(cpu.p & FLAG_CARRY == 0);
+        assert_eq!(6, cycles);
+    }
+
+    #[test]
+    fn decimal_flag_leaves_arithmetic_binary() {
+        // SED; CLC; LDA #$19; ADC #$28
+        let (cpu, _, _) = run(&[0xf8, 0x18, 0xa9, 0x19, 0x69, 0x28], 4);
+        assert_eq!(0x41, cpu.a);
+        assert!(cpu.p & FLAG_DECIMAL != 0);
+        assert!(cpu.p & FLAG_CARRY == 0);
+
+        // SED; SEC; LDA #$10; SBC #$01
+        let (cpu, _, _) = run(&[0xf8, 0x38, 0xa9, 0x10, 0xe9, 0x01], 4);
+        assert_eq!(0x0f, cpu.a);
+        assert!(cpu.p & FLAG_CARRY != 0);
+    }
+
+    #[test]
+    fn undocumented_opcodes_combine_their_operations() {
+        // LAX $10; SAX $11; DCP $12; ISC $13; SLO $14
+        let program = [0xa7, 0x10, 0x87, 0x11, 0xc7, 0x12, 0xe7, 0x13, 0x07, 0x14];
+        let mut bus = FlatBus {
+            ram: vec![0; 0x10000],
+        };
+        bus.ram[0x8000..0x8000 + program.len()].copy_from_slice(&program);
+        bus.ram[0xfffd] = 0x80;
+        bus.ram[0x10..0x15].copy_from_slice(&[0xc3, 0x00, 0xc4, 0x02, 0x81]);
+        let mut cpu = Cpu::new();
+        cpu.reset(&mut bus);
+        let cycles: Vec<u32> = (0..5).map(|_| cpu.step(&mut bus)).collect();
+
+        assert_eq!(vec![3, 3, 5, 5, 5], cycles);
+        assert_eq!(0xc3, cpu.x);
+        assert_eq!(0xc3, bus.ram[0x11]);
+        // $C4 - 1 equals A, so DCP sets Z and C.
+        assert_eq!(0xc3, bus.ram[0x12]);
+        // $C3 - ($02 + 1) with the carry from DCP.
+        assert_eq!(0x03, bus.ram[0x13]);
+        assert_eq!(0x02, bus.ram[0x14]);
+        assert_eq!(0xc2, cpu.a);
+        assert!(cpu.p & FLAG_CARRY != 0);
+    }
+
+    #[test]
+    fn jam_halts_the_cpu_and_ignores_nmi() {
+        let (mut cpu, mut bus, _) = run(&[0xa9, 0x01, 0x02], 2);
+        assert_eq!(0x8002, cpu.pc);
+        cpu.trigger_nmi();
+        assert!(!cpu.is_nmi_pending());
+        cpu.step(&mut bus);
+        assert_eq!(0x8002, cpu.pc);
+    }
+
+    #[test]
+    fn indexed_store_and_load_round_trip() {
+        // LDX #$05; LDA #$42; STA $0200,X; LDY $0205
+        let (cpu, bus, cycles) = run(
+            &[0xa2, 0x05, 0xa9, 0x42, 0x9d, 0x00, 0x02, 0xac, 0x05, 0x02],
+            4,
+        );
+        assert_eq!(0x42, bus.ram[0x205]);
+        assert_eq!(0x42, cpu.y);
+        assert_eq!(2 + 2 + 5 + 4, cycles);
+    }
+
+    #[test]
+    fn subroutine_calls_return_to_the_caller() {
+        // JSR $8005; BRK; BRK; LDA #$01; RTS
+        let (cpu, _, cycles) = run(&[0x20, 0x05, 0x80, 0x00, 0x00, 0xa9, 0x01, 0x60], 3);
+        assert_eq!(0x8003, cpu.pc);
+        assert_eq!(0xfa, cpu.s);
+        assert_eq!(1, cpu.a);
+        assert_eq!(6 + 2 + 6, cycles);
+    }
+
+    #[test]
+    fn taken_branch_across_a_page_costs_two_extra_cycles() {
+        let mut program = vec![0xea; 0x100];
+        // At $80F0: SEC; BCS +$10 (to $8103)
+        program[0xf0] = 0x38;
+        program[0xf1] = 0xb0;
+        program[0xf2] = 0x10;
+        // At $8000: JMP $80F0
+        program[0] = 0x4c;
+        program[1] = 0xf0;
+        program[2] = 0x80;
+        let (cpu, _, cycles) = run(&program, 3);
+        assert_eq!(0x8103, cpu.pc);
+        assert_eq!(3 + 2 + 4, cycles);
+    }
+
+    #[test]
+    fn nmi_pushes_state_and_jumps_through_the_vector() {
+        let mut bus = FlatBus {
+            ram: vec![0; 0x10000],
+        };
+        bus.ram[0xfffa] = 0x34;
+        bus.ram[0xfffb] = 0x12;
+        let mut cpu = Cpu::new();
+        cpu.pc = 0x8000;
+        cpu.p = FLAG_CARRY | FLAG_UNUSED;
+        cpu.trigger_nmi();
+        assert_eq!(7, cpu.step(&mut bus));
+        assert_eq!(0x1234, cpu.pc);
+        assert_eq!(0x80, bus.ram[0x1fd]);
+        assert_eq!(0x00, bus.ram[0x1fc]);
+        assert_eq!(FLAG_CARRY | FLAG_UNUSED, bus.ram[0x1fb]);
+        assert!(cpu.p & FLAG_INTERRUPT != 0);
+    }
+}
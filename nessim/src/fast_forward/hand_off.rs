@@ -0,0 +1,309 @@
+use super::{
+    cpu::{Cpu, FLAG_BREAK, FLAG_UNUSED},
+    ppu::{palette_index, Ppu},
+};
+use crate::{consts::PALETTE_RAM_SIZE, memory::Memory, SimulationState};
+
+/// Where the loader program is served from. Nothing on the transistor-level system's bus decodes
+/// this range, so it's free while the loader runs.
+const LOADER_BASE: u16 = 0x5000;
+/// The `JMP` that spins until the PPU accepts register writes.
+const WAIT_FOR_PPU: u16 = LOADER_BASE;
+const LOAD_REGISTERS: u16 = LOADER_BASE + 3;
+
+/// Give up on a hand-off that hasn't finished after this many half-steps, about four frames.
+const MAX_HAND_OFF_HALF_STEPS: u32 = 4 * 262 * 341 * 8;
+
+/// The nametable byte the loader reads through `$2007` to fill the PPU's read buffer. Its
+/// contents are put back afterwards.
+const READ_BUFFER_ADDR: u16 = 0x2000;
+/// The post-render line, the only one where the VRAM address can be set with rendering enabled and
+/// not be moved by it before vertical blank.
+const POST_RENDER_SCANLINE: u16 = 240;
+
+/// Drives the transistor-level CPU through a loader program that moves the behavioural core's
+/// registers into the netlist.
+///
+/// The loader is served in place of the reset vector and the code it points to. It waits for the
+/// PPU to finish its power-on warm up, then with rendering and NMI off, loads S, fills the `$2007`
+/// read buffer and sets `$2003`. It waits for the post-render line to set the VRAM address, the
+/// scroll, `$2000` and `$2001`, loads A, X and Y, pulls P from a byte it pushed and spins until
+/// the PPU raises NMI at the start of vertical blank. The NMI pushes the loader's own return
+/// address and status, so those writes are replaced by the behavioural CPU's PC and P. Once the
+/// CPU fetches the NMI vector, it is in the same state as the behavioural CPU servicing the same
+/// NMI and the hand-off is complete.
+#[derive(Clone)]
+pub(crate) struct HandOff {
+    loader: Vec<u8>,
+    /// The `JMP` that spins until the post-render line.
+    wait_for_post_render: u16,
+    /// The `JMP` that spins until NMI.
+    wait_for_nmi: u16,
+    stack_pointer: u8,
+    stack_bytes: [u8; 3],
+    has_seen_pre_render: bool,
+    is_loading: bool,
+    is_waiting_for_nmi: bool,
+}
+
+impl HandOff {
+    fn new(cpu: &Cpu, ppu: &Ppu, memory: &Memory) -> Self {
+        let mut loader = vec![0x4c, WAIT_FOR_PPU as u8, (WAIT_FOR_PPU >> 8) as u8];
+        loader.extend_from_slice(&[0xa2, cpu.s, 0x9a]);
+        store(&mut loader, 0x2000, 0x00);
+        store(&mut loader, 0x2001, 0x00);
+        loader.extend_from_slice(&[0x2c, 0x02, 0x20]);
+
+        // A `$2007` read fills the buffer with the byte at the old address, so lend it a nametable
+        // byte to read from.
+        set_vram_address(&mut loader, READ_BUFFER_ADDR);
+        store(&mut loader, 0x2007, ppu.read_buffer);
+        set_vram_address(&mut loader, READ_BUFFER_ADDR);
+        loader.extend_from_slice(&[0xad, 0x07, 0x20]);
+        set_vram_address(&mut loader, READ_BUFFER_ADDR);
+        store(&mut loader, 0x2007, memory.ppu_read(READ_BUFFER_ADDR));
+        store(&mut loader, 0x2003, ppu.oam_addr);
+
+        let wait_for_post_render = LOADER_BASE + loader.len() as u16;
+        loader.extend_from_slice(&[
+            0x4c,
+            wait_for_post_render as u8,
+            (wait_for_post_render >> 8) as u8,
+        ]);
+        // `$2006` can't set the top bit of the VRAM address, but only rendering uses it, and it's
+        // reloaded from the temporary address before the next frame.
+        set_vram_address(&mut loader, ppu.v);
+
+        // Scroll writes rebuild the temporary address and fine x, and `$2000` its nametable bits.
+        let scroll_x = (((ppu.t & 0x001f) << 3) as u8) | ppu.fine_x;
+        let scroll_y = ((((ppu.t >> 5) & 0x001f) << 3) | ((ppu.t >> 12) & 0x0007)) as u8;
+        store(&mut loader, 0x2005, scroll_x);
+        store(&mut loader, 0x2005, scroll_y);
+        if ppu.w {
+            store(&mut loader, 0x2005, scroll_x);
+        }
+        store(&mut loader, 0x2000, ppu.ctrl);
+        store(&mut loader, 0x2001, ppu.mask);
+
+        // Loading A, X and Y sets N and Z, so P is pushed first and pulled last.
+        loader.extend_from_slice(&[0xa9, cpu.p, 0x48]);
+        loader.extend_from_slice(&[0xa9, cpu.a, 0xa2, cpu.x, 0xa0, cpu.y, 0x28]);
+        let wait_for_nmi = LOADER_BASE + loader.len() as u16;
+        loader.extend_from_slice(&[0x4c, wait_for_nmi as u8, (wait_for_nmi >> 8) as u8]);
+
+        HandOff {
+            loader,
+            wait_for_post_render,
+            wait_for_nmi,
+            stack_pointer: cpu.s,
+            stack_bytes: [
+                (cpu.pc >> 8) as u8,
+                cpu.pc as u8,
+                (cpu.p & !FLAG_BREAK) | FLAG_UNUSED,
+            ],
+            has_seen_pre_render: false,
+            is_loading: false,
+            is_waiting_for_nmi: false,
+        }
+    }
+
+    /// The byte to put on the bus in place of memory's for a read of `addr`, if any.
+    fn read(&mut self, addr: u16, vpos: u16) -> Option<u8> {
+        if vpos == 261 {
+            self.has_seen_pre_render = true;
+        }
+
+        match addr {
+            0xfffc if !self.is_loading => Some(WAIT_FOR_PPU as u8),
+            0xfffd if !self.is_loading => Some((WAIT_FOR_PPU >> 8) as u8),
+            a if a == WAIT_FOR_PPU + 1 => {
+                // Leave enough of the frame for the loader to finish before the post-render line.
+                if self.has_seen_pre_render && vpos < 200 {
+                    self.is_loading = true;
+                    Some(LOAD_REGISTERS as u8)
+                } else {
+                    Some(WAIT_FOR_PPU as u8)
+                }
+            }
+            a if a == self.wait_for_post_render + 1 && self.is_loading => {
+                if vpos == POST_RENDER_SCANLINE {
+                    Some((self.wait_for_post_render + 3) as u8)
+                } else {
+                    Some(self.wait_for_post_render as u8)
+                }
+            }
+            a if a >= LOADER_BASE && ((a - LOADER_BASE) as usize) < self.loader.len() => {
+                if a == self.wait_for_nmi && self.is_loading {
+                    self.is_waiting_for_nmi = true;
+                }
+                Some(self.loader[(a - LOADER_BASE) as usize])
+            }
+            _ => None,
+        }
+    }
+
+    /// The byte to store for a write of `val` to `addr`.
+    fn write(&self, addr: u16, val: u8) -> u8 {
+        // The byte pushed for the loader's `PLP` lands where the NMI will push, so only the pushes
+        // made once the loader is done are replaced.
+        if self.is_waiting_for_nmi && addr & 0xff00 == 0x0100 {
+            let depth = self.stack_pointer.wrapping_sub(addr as u8);
+            if let Some(byte) = self.stack_bytes.get(depth as usize) {
+                return *byte;
+            }
+        }
+        val
+    }
+
+    fn is_complete(&self, addr: u16) -> bool {
+        self.is_waiting_for_nmi && addr == 0xfffb
+    }
+}
+
+/// Append `$2006` writes that set the VRAM address to `addr`.
+fn set_vram_address(loader: &mut Vec<u8>, addr: u16) {
+    store(loader, 0x2006, (addr >> 8) as u8);
+    store(loader, 0x2006, addr as u8);
+}
+
+/// Append `LDA #val; STA addr` to `loader`.
+fn store(loader: &mut Vec<u8>, addr: u16, val: u8) {
+    loader.extend_from_slice(&[0xa9, val, 0x8d, addr as u8, (addr >> 8) as u8]);
+}
+
+impl SimulationState {
+    /// Power on the netlist with the behavioural core's memory, palette and OAM, then run it until
+    /// the CPU enters the NMI handler the behavioural CPU was about to enter.
+    pub(crate) fn hand_off_from(&mut self, cpu: &Cpu, ppu: &Ppu, memory: &Memory) {
+        self.init(false);
+        self.memory = memory.clone();
+
+        self.hand_off = Some(HandOff::new(cpu, ppu, memory));
+        let mut is_state_loaded = false;
+        for _ in 0..MAX_HAND_OFF_HALF_STEPS {
+            self.half_step();
+            match &self.hand_off {
+                None => return,
+                // While it powers on, the PPU can write to VRAM and holds a row of OAM on its bit
+                // lines, so the memories are loaded once it has warmed up and before the loader
+                // makes any writes of its own.
+                Some(hand_off) if hand_off.is_loading && !is_state_loaded => {
+                    self.memory = memory.clone();
+                    for addr in 0..PALETTE_RAM_SIZE as u16 {
+                        self.palette_write(addr, ppu.palette[palette_index(addr)]);
+                    }
+                    for (addr, val) in ppu.oam.iter().enumerate() {
+                        self.sprite_write(addr as u16, *val);
+                    }
+                    is_state_loaded = true;
+                }
+                Some(_) => {}
+            }
+        }
+        panic!("The netlist never took the NMI the behavioural CPU was about to take");
+    }
+
+    pub(crate) fn hand_off_read(&mut self, addr: u16) -> Option<u8> {
+        let vpos = self.read_vpos();
+        let hand_off = self.hand_off.as_mut()?;
+        if hand_off.is_complete(addr) {
+            self.hand_off = None;
+            return None;
+        }
+        hand_off.read(addr, vpos)
+    }
+
+    pub(crate) fn hand_off_write(&self, addr: u16, val: u8) -> u8 {
+        match &self.hand_off {
+            Some(hand_off) => hand_off.write(addr, val),
+            None => val,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{consts::NODE_CPU_SYNC, FastForward};
+    use std::fs::File;
+
+    fn hand_off() -> HandOff {
+        let mut cpu = Cpu::new();
+        cpu.a = 0x11;
+        cpu.s = 0xf0;
+        cpu.p = 0xc1 | FLAG_BREAK;
+        cpu.pc = 0x8123;
+        let mut ppu = Ppu::new();
+        ppu.ctrl = 0x90;
+        ppu.t = 0x0c00 | (0x05 << 5) | 0x03;
+        HandOff::new(&cpu, &ppu, &Memory::new())
+    }
+
+    #[test]
+    fn loader_waits_for_the_ppu_before_loading() {
+        let mut hand_off = hand_off();
+        assert_eq!(Some(0x00), hand_off.read(0xfffc, 0));
+        assert_eq!(Some(0x50), hand_off.read(0xfffd, 0));
+        assert_eq!(Some(0x4c), hand_off.read(0x5000, 0));
+        assert_eq!(Some(0x00), hand_off.read(0x5001, 0));
+        assert_eq!(Some(0x00), hand_off.read(0x5001, 261));
+        assert_eq!(Some(0x03), hand_off.read(0x5001, 10));
+        assert_eq!(Some(0xa2), hand_off.read(0x5003, 10));
+        assert_eq!(Some(0xf0), hand_off.read(0x5004, 10));
+        assert_eq!(None, hand_off.read(0xfffc, 10));
+
+        let wait = hand_off.wait_for_post_render;
+        assert_eq!(Some(wait as u8), hand_off.read(wait + 1, 239));
+        assert_eq!(Some((wait + 3) as u8), hand_off.read(wait + 1, 240));
+    }
+
+    #[test]
+    fn nmi_pushes_are_replaced_by_the_behavioural_cpu_state() {
+        let mut hand_off = hand_off();
+        assert_eq!(0x00, hand_off.write(0x01f0, 0x00));
+        hand_off.is_loading = true;
+        // The loader's own push isn't replaced.
+        assert_eq!(0xc1, hand_off.write(0x01f0, 0xc1));
+        hand_off.read(hand_off.wait_for_nmi, 10);
+        assert_eq!(0x81, hand_off.write(0x01f0, 0x50));
+        assert_eq!(0x23, hand_off.write(0x01ef, 0x40));
+        assert_eq!(0xe1, hand_off.write(0x01ee, 0x24));
+        assert_eq!(0x24, hand_off.write(0x01ed, 0x24));
+        assert!(hand_off.is_complete(0xfffb));
+    }
+
+    // The netlist runs for about two frames, so this is left out of the default test run. CI runs
+    // it with `cargo test --release hand_off_continues -- --ignored`.
+    #[test]
+    #[ignore]
+    fn hand_off_continues_from_the_behavioural_state() {
+        let mut sim = SimulationState::new();
+        sim.load_rom(&mut File::open("test_data/scanline.nes").unwrap());
+        let mut fast_forward = FastForward::new(&sim);
+        fast_forward.run_frames(10);
+        fast_forward.hand_off(&mut sim);
+        // Service the NMI the netlist has just taken.
+        fast_forward.step();
+
+        let (cpu, system) = (&fast_forward.cpu, &fast_forward.system);
+        assert_eq!(system.memory.cpu_ram, sim.memory.cpu_ram);
+        assert_eq!(system.memory.nametable_ram, sim.memory.nametable_ram);
+        let palette_ram = sim.palette_ram();
+        for (addr, val) in palette_ram.iter().enumerate() {
+            assert_eq!(system.ppu.palette[palette_index(addr as u16)], *val);
+        }
+        // scanline.nes fills OAM with sprite DMA.
+        assert!(system.ppu.oam.iter().any(|val| *val != 0));
+        let oam = sim.oam();
+        for (addr, val) in system.ppu.oam.iter().enumerate() {
+            // Attribute bits 2 to 4 have no cells.
+            let mask = if addr & 0x03 == 2 { 0xe3 } else { 0xff };
+            assert_eq!(val & mask, oam[addr], "OAM ${:02X}", addr);
+        }
+
+        while !sim.is_node_high(NODE_CPU_SYNC) {
+            sim.half_step();
+        }
+        assert_eq!(cpu.pc, sim.read_cpu_address_bus());
+    }
+}
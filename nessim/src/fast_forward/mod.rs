@@ -0,0 +1,229 @@
+mod cpu;
+mod hand_off;
+mod ppu;
+
+pub(crate) use self::hand_off::HandOff;
+
+use self::{
+    cpu::{Bus, Cpu},
+    ppu::Ppu,
+};
+use crate::{memory::Memory, netlist::Region, SimulationState};
+
+/// A behavioural model of the simulated console for getting to a point of interest at emulator
+/// speed, then handing off to the transistor-level simulation.
+///
+/// It models the same system as the netlist: the 2A03's 6502, without decimal mode, and a 2C02
+/// with the same memory and cartridge. It has the 2A03's sprite DMA, but not its APU or
+/// controller ports.
+///
+/// The hand-off is made at an NMI, where programs expect to pick up at an arbitrary point, and
+/// carries over:
+///
+/// - CPU, nametable, PRG and CHR RAM,
+/// - A, X, Y, S, P and PC,
+/// - palette and OAM,
+/// - `$2000`, `$2001` and `$2003`, and the scroll position and write toggle,
+/// - the VRAM address, but for the top bit that only rendering uses, and the `$2007` read buffer.
+///
+/// The netlist doesn't render the frame the registers are loaded in, so the sprite 0 hit and
+/// sprite overflow flags are clear when the NMI is taken.
+#[derive(Clone)]
+pub struct FastForward {
+    cpu: Cpu,
+    system: System,
+}
+
+/// Everything on the behavioural CPU's bus.
+#[derive(Clone)]
+struct System {
+    ppu: Ppu,
+    memory: Memory,
+    open_bus: u8,
+    /// The page a write to `$4014` has asked to be copied to OAM.
+    dma_page: Option<u8>,
+}
+
+impl Bus for System {
+    fn read(&mut self, addr: u16) -> u8 {
+        let val = match addr {
+            0x2000..=0x3fff => self.ppu.read_register(addr, &self.memory),
+            _ => match self.memory.cpu_read(addr) {
+                (_, true) => self.open_bus,
+                (val, false) => val,
+            },
+        };
+        self.open_bus = val;
+        val
+    }
+
+    fn write(&mut self, addr: u16, val: u8) {
+        self.open_bus = val;
+        match addr {
+            0x2000..=0x3fff => self.ppu.write_register(addr, val, &mut self.memory),
+            0x4014 => self.dma_page = Some(val),
+            _ => self.memory.cpu_write(addr, val),
+        }
+    }
+}
+
+impl FastForward {
+    /// Power on a behavioural console with the cartridge loaded into `sim`, its RAMs filled as
+    /// `sim`'s power-on options say.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sim` isn't an NTSC console, the only region the behavioural model times.
+    pub fn new(sim: &SimulationState) -> Self {
+        assert_eq!(
+            Region::Ntsc,
+            sim.region(),
+            "only NTSC consoles can be fast-forwarded"
+        );
+        let mut memory = sim.memory.clone();
+        memory.power_on(&sim.power_on_options());
+
+        let mut system = System {
+            ppu: Ppu::new(),
+            memory,
+            open_bus: 0,
+            dma_page: None,
+        };
+        let mut cpu = Cpu::new();
+        cpu.reset(&mut system);
+        FastForward { cpu, system }
+    }
+
+    /// The number of vertical blanks started since power-on.
+    pub fn frame(&self) -> u64 {
+        self.system.ppu.frame
+    }
+
+    /// CPU cycles executed since power-on.
+    pub fn cpu_cycles(&self) -> u64 {
+        self.cpu.cycles
+    }
+
+    /// Run until `frames` more vertical blanks have started.
+    pub fn run_frames(&mut self, frames: u64) {
+        let end = self.frame() + frames;
+        while self.frame() < end {
+            self.step();
+        }
+    }
+
+    /// Run until the CPU is about to take an NMI, then make `sim` continue from there.
+    ///
+    /// This runs `sim` for up to two frames while a loader program moves the CPU and PPU registers
+    /// into the netlist, and replaces whatever state `sim` was in.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the program leaves NMIs disabled for two frames.
+    pub fn hand_off(&mut self, sim: &mut SimulationState) {
+        let end = self.frame() + 2;
+        while !self.cpu.is_nmi_pending() {
+            assert!(
+                self.frame() <= end,
+                "NMIs are disabled, so there's nowhere to hand off"
+            );
+            self.step();
+        }
+        sim.hand_off_from(&self.cpu, &self.system.ppu, &self.system.memory);
+    }
+
+    fn step(&mut self) {
+        let cycles = self.cpu.step(&mut self.system);
+        self.tick_ppu(cycles);
+        if let Some(page) = self.system.dma_page.take() {
+            self.sprite_dma(page);
+        }
+        if self.system.ppu.take_nmi() {
+            self.cpu.trigger_nmi();
+        }
+    }
+
+    /// Copy a page to OAM through `$2004`, one byte every two cycles.
+    ///
+    /// The CPU is halted for a cycle first, and for another if that lands on an odd cycle since
+    /// power-on, as the DMA unit reads on even cycles and writes on odd ones. That comes to 513 or
+    /// 514 cycles.
+    fn sprite_dma(&mut self, page: u8) {
+        let halt_cycles = if self.cpu.cycles % 2 == 1 { 2 } else { 1 };
+        self.tick_ppu(halt_cycles);
+        let start = u16::from(page) << 8;
+        for addr in start..=start | 0xff {
+            let val = self.system.read(addr);
+            self.tick_ppu(1);
+            self.system.write(0x2004, val);
+            self.tick_ppu(1);
+        }
+        self.cpu.cycles += u64::from(halt_cycles + 512);
+    }
+
+    fn tick_ppu(&mut self, cpu_cycles: u32) {
+        for _ in 0..cpu_cycles * 3 {
+            self.system.ppu.tick(&self.system.memory);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PowerOnOptions, RamFill};
+
+    #[test]
+    fn ram_powers_on_with_the_netlist_fills() {
+        let mut sim = SimulationState::new();
+        sim.set_power_on_options(PowerOnOptions {
+            cpu_ram: RamFill::Alternating,
+            nametable_ram: RamFill::Ones,
+            ..PowerOnOptions::default()
+        });
+
+        let fast_forward = FastForward::new(&sim);
+        let memory = &fast_forward.system.memory;
+        assert_eq!([0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff], memory.cpu_ram[..8]);
+        assert!(memory.nametable_ram.iter().flatten().all(|b| *b == 0xff));
+    }
+
+    /// Step a console whose cartridge runs `program` from `$8000`, with `$0200` to `$02FF` counting
+    /// up, returning the cycles each step took.
+    fn run_program(program: &[u8], steps: usize) -> (FastForward, Vec<u64>) {
+        let mut sim = SimulationState::new();
+        sim.memory.prg_ram[..program.len()].copy_from_slice(program);
+        sim.memory.prg_ram[0x7ffd] = 0x80;
+        let mut fast_forward = FastForward::new(&sim);
+        for (i, b) in fast_forward.system.memory.cpu_ram[0x200..0x300]
+            .iter_mut()
+            .enumerate()
+        {
+            *b = i as u8;
+        }
+
+        let cycles = (0..steps)
+            .map(|_| {
+                let start = fast_forward.cpu_cycles();
+                fast_forward.step();
+                fast_forward.cpu_cycles() - start
+            })
+            .collect();
+        (fast_forward, cycles)
+    }
+
+    #[test]
+    fn sprite_dma_copies_a_page_to_oam_and_stalls_the_cpu() {
+        // LDA #$02; STA $4014, ending on cycle 13 after the reset sequence's 7.
+        let (fast_forward, cycles) = run_program(&[0xa9, 0x02, 0x8d, 0x14, 0x40], 2);
+        assert_eq!(vec![2, 4 + 514], cycles);
+        assert_eq!(
+            fast_forward.system.memory.cpu_ram[0x200..0x300],
+            fast_forward.system.ppu.oam[..]
+        );
+
+        // LDA $00; LDA #$02; STA $4014, ending on an even cycle.
+        let (_, cycles) = run_program(&[0xa5, 0x00, 0xa9, 0x02, 0x8d, 0x14, 0x40], 3);
+        assert_eq!(vec![3, 2, 4 + 513], cycles);
+    }
+}
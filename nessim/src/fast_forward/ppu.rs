@@ -0,0 +1,413 @@
+use crate::memory::Memory;
+
+pub const DOTS_PER_SCANLINE: u16 = 341;
+pub const SCANLINES_PER_FRAME: u16 = 262;
+const VBLANK_SCANLINE: u16 = 241;
+const PRE_RENDER_SCANLINE: u16 = 261;
+
+const STATUS_SPRITE_OVERFLOW: u8 = 0x20;
+const STATUS_SPRITE0_HIT: u8 = 0x40;
+const STATUS_VBLANK: u8 = 0x80;
+
+/// A dot-stepped model of the 2C02's registers and timing.
+///
+/// Pixels aren't produced. Rendering is only modelled as far as a program can observe it: the
+/// scroll registers, sprite 0 hit and sprite overflow.
+#[derive(Clone)]
+pub struct Ppu {
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_addr: u8,
+    /// The current VRAM address.
+    pub v: u16,
+    /// The temporary VRAM address, which holds the scroll position while rendering.
+    pub t: u16,
+    pub fine_x: u8,
+    /// The write toggle shared by `$2005` and `$2006`.
+    pub w: bool,
+    pub read_buffer: u8,
+    pub oam: [u8; 256],
+    pub palette: [u8; 32],
+    pub scanline: u16,
+    pub dot: u16,
+    /// The number of vertical blanks started since power-on.
+    pub frame: u64,
+    latch: u8,
+    nmi_output: bool,
+    nmi_edge: bool,
+    sprite0_hit_dot: Option<u16>,
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            w: false,
+            read_buffer: 0,
+            oam: [0; 256],
+            palette: [0; 32],
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            latch: 0,
+            nmi_output: false,
+            nmi_edge: false,
+            sprite0_hit_dot: None,
+        }
+    }
+
+    fn is_rendering_enabled(&self) -> bool {
+        self.mask & 0x18 != 0
+    }
+
+    /// Whether the NMI output went active since the last call.
+    pub fn take_nmi(&mut self) -> bool {
+        let edge = self.nmi_edge;
+        self.nmi_edge = false;
+        edge
+    }
+
+    fn update_nmi(&mut self) {
+        let output = self.ctrl & 0x80 != 0 && self.status & STATUS_VBLANK != 0;
+        if output && !self.nmi_output {
+            self.nmi_edge = true;
+        }
+        self.nmi_output = output;
+    }
+
+    pub fn read_register(&mut self, addr: u16, memory: &Memory) -> u8 {
+        match addr & 7 {
+            2 => {
+                let val = self.status | (self.latch & 0x1f);
+                self.status &= !STATUS_VBLANK;
+                self.w = false;
+                self.update_nmi();
+                self.latch = val;
+            }
+            4 => self.latch = self.oam[self.oam_addr as usize],
+            7 => {
+                let addr = self.v & 0x3fff;
+                self.latch = if addr >= 0x3f00 {
+                    // Palette reads bypass the buffer, which is filled from the nametable below.
+                    self.read_buffer = memory.ppu_read(addr - 0x1000);
+                    self.palette[palette_index(addr)]
+                } else {
+                    let val = self.read_buffer;
+                    self.read_buffer = memory.ppu_read(addr);
+                    val
+                };
+                self.increment_v();
+            }
+            _ => {}
+        }
+        self.latch
+    }
+
+    pub fn write_register(&mut self, addr: u16, val: u8, memory: &mut Memory) {
+        self.latch = val;
+        match addr & 7 {
+            0 => {
+                self.ctrl = val;
+                self.t = (self.t & !0x0c00) | (u16::from(val & 0x03) << 10);
+                self.update_nmi();
+            }
+            1 => self.mask = val,
+            3 => self.oam_addr = val,
+            4 => {
+                self.oam[self.oam_addr as usize] = val;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            5 => {
+                if self.w {
+                    self.t = (self.t & !0x73e0)
+                        | (u16::from(val & 0x07) << 12)
+                        | (u16::from(val & 0xf8) << 2);
+                } else {
+                    self.t = (self.t & !0x001f) | u16::from(val >> 3);
+                    self.fine_x = val & 0x07;
+                }
+                self.w = !self.w;
+            }
+            6 => {
+                if self.w {
+                    self.t = (self.t & 0xff00) | u16::from(val);
+                    self.v = self.t;
+                } else {
+                    self.t = (self.t & 0x00ff) | (u16::from(val & 0x3f) << 8);
+                }
+                self.w = !self.w;
+            }
+            7 => {
+                let addr = self.v & 0x3fff;
+                if addr >= 0x3f00 {
+                    self.palette[palette_index(addr)] = val & 0x3f;
+                } else {
+                    memory.ppu_write(addr, val);
+                }
+                self.increment_v();
+            }
+            _ => {}
+        }
+    }
+
+    fn increment_v(&mut self) {
+        let step = if self.ctrl & 0x04 != 0 { 32 } else { 1 };
+        self.v = self.v.wrapping_add(step) & 0x7fff;
+    }
+
+    /// Advance by one dot.
+    pub fn tick(&mut self, memory: &Memory) {
+        self.dot += 1;
+        if self.dot == DOTS_PER_SCANLINE
+            || (self.scanline == PRE_RENDER_SCANLINE
+                && self.dot == DOTS_PER_SCANLINE - 1
+                && self.frame & 1 == 1
+                && self.is_rendering_enabled())
+        {
+            // The last dot of the pre-render line is skipped on odd frames while rendering.
+            self.dot = 0;
+            self.scanline = (self.scanline + 1) % SCANLINES_PER_FRAME;
+        }
+
+        let dot = self.dot;
+        let scanline = self.scanline;
+        if scanline == VBLANK_SCANLINE && dot == 1 {
+            self.status |= STATUS_VBLANK;
+            self.frame += 1;
+            self.update_nmi();
+        } else if scanline == PRE_RENDER_SCANLINE && dot == 1 {
+            self.status &= !(STATUS_VBLANK | STATUS_SPRITE0_HIT | STATUS_SPRITE_OVERFLOW);
+            self.update_nmi();
+        }
+
+        if !self.is_rendering_enabled() || (scanline >= 240 && scanline != PRE_RENDER_SCANLINE) {
+            return;
+        }
+
+        if scanline < 240 {
+            if dot == 1 {
+                self.sprite0_hit_dot = self.find_sprite0_hit(memory);
+            }
+            if Some(dot) == self.sprite0_hit_dot {
+                self.status |= STATUS_SPRITE0_HIT;
+            }
+            if dot == 257 && self.count_sprites_on(scanline) > 8 {
+                self.status |= STATUS_SPRITE_OVERFLOW;
+            }
+        }
+
+        if (257..=320).contains(&dot) {
+            self.oam_addr = 0;
+        }
+
+        if ((1..=256).contains(&dot) || dot == 328 || dot == 336) && dot & 0x07 == 0 {
+            self.increment_coarse_x();
+        }
+        if dot == 256 {
+            self.increment_y();
+        } else if dot == 257 {
+            self.v = (self.v & !0x041f) | (self.t & 0x041f);
+        } else if scanline == PRE_RENDER_SCANLINE && (280..=304).contains(&dot) {
+            self.v = (self.v & !0x7be0) | (self.t & 0x7be0);
+        }
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.v & 0x001f == 31 {
+            self.v = (self.v & !0x001f) ^ 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03e0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03e0) | (coarse_y << 5);
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl & 0x20 != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    fn count_sprites_on(&self, scanline: u16) -> usize {
+        let height = self.sprite_height();
+        self.oam
+            .chunks(4)
+            .filter(|sprite| {
+                let top = u16::from(sprite[0]);
+                scanline >= top && scanline < top + height
+            })
+            .count()
+    }
+
+    /// The dot on the current scanline at which an opaque pixel of sprite 0 overlaps an opaque
+    /// background pixel, if any.
+    fn find_sprite0_hit(&self, memory: &Memory) -> Option<u16> {
+        if self.mask & 0x18 != 0x18 || self.status & STATUS_SPRITE0_HIT != 0 {
+            return None;
+        }
+
+        // Sprites are evaluated a line ahead, so they appear one line below their OAM position.
+        let top = u16::from(self.oam[0]) + 1;
+        let height = self.sprite_height();
+        if self.scanline < top || self.scanline >= top + height {
+            return None;
+        }
+
+        let tile = self.oam[1];
+        let attributes = self.oam[2];
+        let mut row = self.scanline - top;
+        if attributes & 0x80 != 0 {
+            row = height - 1 - row;
+        }
+        let pattern_addr = if height == 16 {
+            let table = u16::from(tile & 0x01) << 12;
+            let tile = u16::from(tile & 0xfe) + (row >> 3);
+            table | (tile << 4) | (row & 0x07)
+        } else {
+            let table = u16::from(self.ctrl & 0x08) << 9;
+            table | (u16::from(tile) << 4) | row
+        };
+        let sprite_pattern = memory.ppu_read(pattern_addr) | memory.ppu_read(pattern_addr + 8);
+
+        // The first tile of the line was fetched two tiles before the current position.
+        let column = ((self.v & 0x001f) | ((self.v & 0x0400) >> 5)).wrapping_sub(2) & 0x3f;
+        let fine_y = (self.v >> 12) & 0x07;
+        let background_table = u16::from(self.ctrl & 0x10) << 8;
+
+        for i in 0..8 {
+            let x = u16::from(self.oam[3]) + i;
+            if x >= 255 {
+                break;
+            }
+            if x < 8 && self.mask & 0x06 != 0x06 {
+                continue;
+            }
+
+            let bit = if attributes & 0x40 != 0 { i } else { 7 - i };
+            if sprite_pattern & (1 << bit) == 0 {
+                continue;
+            }
+
+            let position = x + u16::from(self.fine_x);
+            let tile_column = (column + (position >> 3)) & 0x3f;
+            let nametable_addr = 0x2000
+                | (self.v & 0x0800)
+                | ((tile_column & 0x20) << 5)
+                | (self.v & 0x03e0)
+                | (tile_column & 0x1f);
+            let background_tile = u16::from(memory.ppu_read(nametable_addr));
+            let background_addr = background_table | (background_tile << 4) | fine_y;
+            let background_pattern =
+                memory.ppu_read(background_addr) | memory.ppu_read(background_addr + 8);
+            if background_pattern & (0x80 >> (position & 0x07)) != 0 {
+                return Some(x + 1);
+            }
+        }
+
+        None
+    }
+}
+
+pub(super) fn palette_index(addr: u16) -> usize {
+    let index = (addr & 0x1f) as usize;
+    // The backdrop entries of the sprite palettes mirror those of the background palettes.
+    if index & 0x13 == 0x10 {
+        index & !0x10
+    } else {
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scroll_writes_fill_the_temporary_address() {
+        let mut memory = Memory::new();
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2000, 0x02, &mut memory);
+        ppu.write_register(0x2005, 0x7d, &mut memory);
+        ppu.write_register(0x2005, 0x5e, &mut memory);
+        // Fine y 6, nametable 2, coarse y 11, coarse x 15.
+        assert_eq!(0x696f, ppu.t);
+        assert_eq!(0x05, ppu.fine_x);
+        assert!(!ppu.w);
+
+        ppu.write_register(0x2006, 0x3d, &mut memory);
+        ppu.write_register(0x2006, 0xf0, &mut memory);
+        assert_eq!(0x3df0, ppu.v);
+        assert_eq!(ppu.t, ppu.v);
+    }
+
+    #[test]
+    fn data_reads_are_buffered_except_for_the_palette() {
+        let mut memory = Memory::new();
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2006, 0x20, &mut memory);
+        ppu.write_register(0x2006, 0x00, &mut memory);
+        ppu.write_register(0x2007, 0x11, &mut memory);
+        ppu.write_register(0x2006, 0x3f, &mut memory);
+        ppu.write_register(0x2006, 0x10, &mut memory);
+        ppu.write_register(0x2007, 0x22, &mut memory);
+        assert_eq!(0x22, ppu.palette[0]);
+
+        ppu.write_register(0x2006, 0x20, &mut memory);
+        ppu.write_register(0x2006, 0x00, &mut memory);
+        ppu.read_register(0x2007, &memory);
+        assert_eq!(0x11, ppu.read_register(0x2007, &memory));
+    }
+
+    #[test]
+    fn vblank_raises_nmi_once_per_frame() {
+        let memory = Memory::new();
+        let mut ppu = Ppu::new();
+        ppu.ctrl = 0x80;
+        let mut nmis = 0;
+        for _ in 0..(2 * u32::from(DOTS_PER_SCANLINE) * u32::from(SCANLINES_PER_FRAME)) {
+            ppu.tick(&memory);
+            if ppu.take_nmi() {
+                assert_eq!((VBLANK_SCANLINE, 1), (ppu.scanline, ppu.dot));
+                nmis += 1;
+            }
+        }
+        assert_eq!(2, nmis);
+        assert_eq!(2, ppu.frame);
+    }
+
+    #[test]
+    fn enabling_nmi_during_vblank_raises_nmi() {
+        let mut memory = Memory::new();
+        let mut ppu = Ppu::new();
+        ppu.status = STATUS_VBLANK;
+        ppu.write_register(0x2000, 0x80, &mut memory);
+        assert!(ppu.take_nmi());
+        assert!(!ppu.take_nmi());
+    }
+}
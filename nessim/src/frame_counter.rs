@@ -0,0 +1,62 @@
+/// The number of scanlines the PPU outputs pixels on.
+pub const VISIBLE_SCANLINES: u16 = 240;
+
+/// Counts the frames the PPU finishes outputting, watching for the vertical counter to leave the
+/// visible area.
+#[derive(Clone)]
+pub struct FrameCounter {
+    prev_vpos: u16,
+    count: u64,
+    completed: bool,
+}
+
+impl FrameCounter {
+    pub fn new() -> Self {
+        FrameCounter {
+            prev_vpos: 0,
+            count: 0,
+            completed: false,
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Whether the frame was completed during the current half-step.
+    pub fn is_completed(&self) -> bool {
+        self.completed
+    }
+
+    pub fn begin_half_step(&mut self) {
+        self.completed = false;
+    }
+
+    /// Note the vertical counter at the start of a new dot.
+    pub fn update(&mut self, vpos: u16) {
+        if vpos >= VISIBLE_SCANLINES && self.prev_vpos < VISIBLE_SCANLINES {
+            self.count += 1;
+            self.completed = true;
+        }
+        self.prev_vpos = vpos;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_complete_when_the_last_visible_line_ends() {
+        // NTSC frames have 262 lines, PAL and Dendy frames 312.
+        for lines in [262, 312] {
+            let mut counter = FrameCounter::new();
+            for vpos in (0..lines).chain(0..241) {
+                counter.begin_half_step();
+                counter.update(vpos);
+                assert_eq!(vpos == VISIBLE_SCANLINES, counter.is_completed());
+            }
+            assert_eq!(2, counter.count());
+        }
+    }
+}
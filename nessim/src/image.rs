@@ -0,0 +1,249 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// The width of the frames the PPU outputs.
+pub const FRAME_WIDTH: usize = 256;
+/// The height of the frames the PPU outputs.
+pub const FRAME_HEIGHT: usize = 240;
+
+pub(crate) const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+/// The most a stored deflate block can hold.
+const MAX_STORED_BLOCK: usize = 0xffff;
+
+/// How many pixels to cut from each edge of a frame before saving it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crop {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+impl Crop {
+    /// Keep the whole frame.
+    pub const NONE: Crop = Crop {
+        top: 0,
+        bottom: 0,
+        left: 0,
+        right: 0,
+    };
+
+    /// Cut the 8 pixels on each edge that a television hides behind its bezel, where games often
+    /// leave garbage.
+    pub const OVERSCAN: Crop = Crop {
+        top: 8,
+        bottom: 8,
+        left: 8,
+        right: 8,
+    };
+
+    pub fn width(&self) -> usize {
+        FRAME_WIDTH.saturating_sub(self.left + self.right)
+    }
+
+    pub fn height(&self) -> usize {
+        FRAME_HEIGHT.saturating_sub(self.top + self.bottom)
+    }
+
+    /// The part of a 256x240 frame that's kept, in rows of `width` pixels.
+    pub fn apply<T: Copy>(&self, frame: &[T]) -> Vec<T> {
+        assert_eq!(FRAME_WIDTH * FRAME_HEIGHT, frame.len(), "not a whole frame");
+        frame
+            .chunks(FRAME_WIDTH)
+            .skip(self.top)
+            .take(self.height())
+            .flat_map(|row| row.iter().skip(self.left).take(self.width()))
+            .cloned()
+            .collect()
+    }
+}
+
+/// An ARGB picture in rows from the top, such as the views of the PPU's memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub argb: Vec<u32>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            argb: vec![0; width * height],
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.argb[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, argb: u32) {
+        self.argb[y * self.width + x] = argb;
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write_png(&mut file, &self.argb, self.width)?;
+        file.flush()
+    }
+}
+
+/// Write a 256x240 ARGB frame, such as `SimulationState::framebuffer` or a copy of it taken when a
+/// frame completed, as a PNG.
+pub fn write_frame_png<W: Write>(writer: &mut W, frame: &[u32], crop: Crop) -> io::Result<()> {
+    write_png(writer, &crop.apply(frame), crop.width())
+}
+
+/// Write a 256x240 ARGB frame as a binary PPM.
+pub fn write_frame_ppm<W: Write>(writer: &mut W, frame: &[u32], crop: Crop) -> io::Result<()> {
+    write_ppm(writer, &crop.apply(frame), crop.width())
+}
+
+/// Write ARGB pixels in rows of `width` as an RGB PNG. The alpha channel is ignored.
+pub fn write_png<W: Write>(writer: &mut W, argb: &[u32], width: usize) -> io::Result<()> {
+    let height = argb.len().checked_div(width).unwrap_or(0);
+    writer.write_all(&PNG_SIGNATURE)?;
+    write_png_chunk(writer, b"IHDR", &png_header(width, height))?;
+    write_png_chunk(writer, b"IDAT", &zlib_stored(&png_scanlines(argb, width)))?;
+    write_png_chunk(writer, b"IEND", &[])
+}
+
+/// Write ARGB pixels in rows of `width` as a binary (P6) PPM. The alpha channel is ignored.
+pub fn write_ppm<W: Write>(writer: &mut W, argb: &[u32], width: usize) -> io::Result<()> {
+    let height = argb.len().checked_div(width).unwrap_or(0);
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+    let rgb = argb[..width * height]
+        .iter()
+        .flat_map(|pixel| rgb(*pixel))
+        .collect::<Vec<u8>>();
+    writer.write_all(&rgb)
+}
+
+fn rgb(argb: u32) -> [u8; 3] {
+    [(argb >> 16) as u8, (argb >> 8) as u8, argb as u8]
+}
+
+/// An `IHDR` chunk's contents for 8-bit RGB.
+pub(crate) fn png_header(width: usize, height: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    header
+}
+
+/// The rows of an RGB PNG, unfiltered, ready to be compressed.
+pub(crate) fn png_scanlines(argb: &[u32], width: usize) -> Vec<u8> {
+    let mut scanlines = Vec::with_capacity(argb.len() * 3 + argb.len() / width.max(1));
+    for row in argb.chunks_exact(width.max(1)) {
+        scanlines.push(0);
+        scanlines.extend(row.iter().flat_map(|pixel| rgb(*pixel)));
+    }
+    scanlines
+}
+
+pub(crate) fn write_png_chunk<W: Write>(
+    writer: &mut W,
+    kind: &[u8; 4],
+    data: &[u8],
+) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32(crc32_update(!0, kind), data);
+    writer.write_all(&crc.to_be_bytes())
+}
+
+/// Wrap data in a zlib stream of stored deflate blocks. Frames are small enough that leaving
+/// them uncompressed saves pulling in a compressor.
+pub(crate) fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let blocks = data.len() / MAX_STORED_BLOCK + 1;
+    let mut stream = Vec::with_capacity(data.len() + blocks * 5 + 6);
+    stream.extend_from_slice(&[0x78, 0x01]);
+    let mut chunks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if chunks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(chunk);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (mut a, mut b) = (1, 0);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += u32::from(*byte);
+            b += a;
+        }
+        a %= MODULUS;
+        b %= MODULUS;
+    }
+    b << 16 | a
+}
+
+/// Finish a CRC-32 started with `crc32_update(!0, ..)`.
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !crc32_update(crc, data)
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0xedb8_8320
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(0xae42_6082, crc32(crc32_update(!0, b"IEND"), &[]));
+        assert_eq!(0x11e6_0398, adler32(b"Wikipedia"));
+    }
+
+    #[test]
+    fn cropped_frames_are_written_with_their_size() {
+        let frame = (0..(FRAME_WIDTH * FRAME_HEIGHT) as u32).collect::<Vec<u32>>();
+        let cropped = Crop::OVERSCAN.apply(&frame);
+        assert_eq!(240 * 224, cropped.len());
+        assert_eq!(8 * 256 + 8, cropped[0]);
+
+        let mut ppm = Vec::new();
+        write_frame_ppm(&mut ppm, &frame, Crop::OVERSCAN).unwrap();
+        assert!(ppm.starts_with(b"P6\n240 224\n255\n"));
+        assert_eq!(15 + 240 * 224 * 3, ppm.len());
+
+        let mut png = Vec::new();
+        write_frame_png(&mut png, &frame, Crop::NONE).unwrap();
+        assert_eq!(PNG_SIGNATURE, png[..8]);
+        assert_eq!(b"IHDR", &png[12..16]);
+        assert_eq!(
+            256,
+            u32::from_be_bytes([png[16], png[17], png[18], png[19]])
+        );
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+}
@@ -1,19 +1,76 @@
+mod bit_set;
+mod bit_sliced;
+mod channel_components;
+#[cfg(feature = "compiled")]
+mod compiled;
 mod components;
 mod consts;
+mod cpu_only;
+mod event_log;
+mod fast_forward;
+mod frame_counter;
+mod image;
+mod memory;
+mod netlist;
+mod node_group;
+mod ntsc;
+mod palette;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod power_on;
+mod ppu_only;
 mod preprocessor;
 mod processed_nodes_map;
 mod recalc_swap_list;
+mod recorder;
+#[cfg(feature = "stats")]
+mod stats;
+mod viewers;
 
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "stats")]
+pub use crate::stats::{RecalcCounts, SimulationStats};
+pub use crate::{
+    bit_sliced::{BitSlicedSimulation, LANES},
+    channel_components::{ChannelComponents, LOCAL_GND, LOCAL_PWR},
+    cpu_only::CpuSimulation,
+    event_log::{PpuEvent, PpuEventKind, PpuEventLog},
+    fast_forward::FastForward,
+    image::{
+        write_frame_png, write_frame_ppm, write_png, write_ppm, Crop, Image, FRAME_HEIGHT,
+        FRAME_WIDTH,
+    },
+    netlist::{MissingNetlistFile, Region},
+    ntsc::{CompositeFrame, SAMPLES_PER_DOT, SUBCARRIER_PHASES},
+    palette::{LoadPaletteError, NtscPaletteOptions, OutputMode, Palette, PALETTE_ENTRIES},
+    power_on::{ClockAlignment, PowerOnOptions, RamFill},
+    ppu_only::{
+        ParseScriptError, PpuSimulation, RecordedRead, RegisterAccess, RegisterScript,
+        ScriptCommand,
+    },
+    recorder::{FrameRecorder, RecorderOptions, RecordingFormat},
+    viewers::PATTERN_TABLE_SIZE,
+};
+
 use crate::{
-    components::{Node, Transistor},
+    bit_set::BitSet,
     consts::*,
-    processed_nodes_map::ProcessedNodesSet,
+    fast_forward::HandOff,
+    frame_counter::{FrameCounter, VISIBLE_SCANLINES},
+    memory::{Memory, MirroringType},
+    netlist::{Chips, Netlist},
+    node_group::NodeGroup,
+    power_on::Rng,
     recalc_swap_list::RecalcSwapList,
 };
-use std::io::{Read, Seek};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Seek, Write},
+    path::Path,
+    sync::Arc,
+};
 
 #[allow(dead_code)]
 enum MemoryType {
@@ -34,98 +91,234 @@ enum MemoryType {
     FullState,
 }
 
-#[allow(dead_code)]
-enum MirroringType {
-    Horizontal,
-    Vertical,
-    FourScreens,
-    ScreenAOnly,
-    ScreenBOnly,
-}
-
+/// The complete state of a running simulation.
+///
+/// The state owns all of its data, so it can be moved to a worker thread, and cloning it yields an
+/// independent simulation that continues from the same point. The netlist and other data that never
+/// change after construction are shared between clones, so forking a running simulation only copies
+/// node and transistor states and the memory buffers.
+#[derive(Clone)]
 pub struct SimulationState {
-    all_recalc_nodes: Vec<u16>,
-    transistors_initial_power_state: Vec<bool>,
-    nodes: Vec<Node>,
-    has_ground: bool,
-    has_power: bool,
-    group: Vec<u16>,
-    node_counts: Vec<u8>,
-    transistors: Vec<Transistor>,
-    nodes_c1_c2: Vec<Vec<u16>>,
-    processed_nodes: ProcessedNodesSet,
+    netlist: Arc<Netlist>,
+    node_state: BitSet,
+    node_pullup: BitSet,
+    node_pulldown: BitSet,
+    transistor_on: BitSet,
+    group: NodeGroup,
     step_cycle_count: u8,
     prev_ppu_ale: bool,
     prev_ppu_write: bool,
     prev_ppu_read: bool,
     chr_address: u16,
-    mirroring_type: MirroringType,
-    chr_ram: Box<[u8; 0x2000]>,
-    nametable_ram: Box<[[u8; 0x400]; 4]>,
-    cpu_ram: Box<[u8; 0x800]>,
-    prg_ram: Box<[u8; 0x8000]>,
-    last_cpu_db_value: u8,
+    memory: Memory,
     last_data: u8,
     prev_hpos: i32,
     ppu_framebuffer: Box<[u32; 256 * 240]>,
-    sprite_nodes: Vec<Vec<(i32, i32)>>,
-    palette_nodes: Vec<Vec<(i32, i32)>>,
+    raw_framebuffer: Box<[u16; 256 * 240]>,
+    output_mode: OutputMode,
+    frame_counter: FrameCounter,
+    palette: Palette,
+    /// The phase of the colour subcarrier, in master clock edges since power-on.
+    subcarrier_phase: u8,
+    /// The composite signal of the current frame, when it's being output.
+    composite: Option<Box<CompositeFrame>>,
+    /// What the PPU has done, when it's being logged.
+    event_log: Option<Box<PpuEventLog>>,
     recalc_swap_list: RecalcSwapList,
+    power_on: PowerOnOptions,
+    /// The clock alignment picked at the last power-on.
+    clock_alignment: u8,
+    /// How many half-steps the reset line is held low for by `reset` and `power_cycle`.
+    reset_hold_half_steps: u32,
+    /// Set while a `FastForward` is handing its state over to the netlist.
+    hand_off: Option<HandOff>,
+    #[cfg(feature = "parallel")]
+    parallel_recalc: Option<parallel::ParallelRecalc>,
+    #[cfg(feature = "compiled")]
+    compiled_tables: bool,
+    #[cfg(feature = "stats")]
+    stats: SimulationStats,
 }
 
 impl SimulationState {
     pub fn new() -> Self {
-        use crate::preprocessor::{
-            id_conversion_table, load_ppu_nodes, load_segment_definitions,
-            load_transistor_definitions, setup_nodes, setup_transistors,
-        };
-        let conversion_table = id_conversion_table();
-        let seg_defs = load_segment_definitions(&conversion_table);
-        let trans_defs = load_transistor_definitions(&conversion_table);
-        let mut nodes = setup_nodes(&seg_defs);
-        let (palette_nodes, sprite_nodes) = load_ppu_nodes();
-        let transistors_initial_power_state = trans_defs
-            .iter()
-            .map(|def| def.gate == NODE_PWR)
-            .collect::<Vec<bool>>();
-        let (transistors, node_counts, nodes_c1_c2, _) = setup_transistors(&mut nodes, trans_defs);
-        let all_recalc_nodes = nodes
-            .iter()
-            .filter(|n| n.num != NODE_PWR && n.num != NODE_GND && n.num != EMPTYNODE)
-            .map(|n| n.num)
-            .collect::<Vec<u16>>();
+        Self::with_netlist(Arc::new(Netlist::load()))
+    }
+
+    /// Simulate a console of `region`, if its netlist is under `data/`.
+    pub fn with_region(region: Region) -> Result<Self, MissingNetlistFile> {
+        let netlist = Netlist::load_region(Chips::Both, region)?;
+        Ok(Self::with_netlist(Arc::new(netlist)))
+    }
+
+    pub fn region(&self) -> Region {
+        self.netlist.region
+    }
+
+    fn with_netlist(netlist: Arc<Netlist>) -> Self {
+        let node_count = netlist.node_count();
+        let transistor_count = netlist.transistor_count();
+        let palette = Palette::for_region(netlist.region);
 
         SimulationState {
-            all_recalc_nodes,
-            transistors_initial_power_state,
-            nodes: nodes.into_iter().map(|def| Node::new(def)).collect(),
-            node_counts,
-            has_ground: false,
-            has_power: false,
-            group: Vec::new(),
-            transistors,
-            nodes_c1_c2,
-            processed_nodes: ProcessedNodesSet::new(NUM_NODES),
+            node_state: BitSet::new(node_count),
+            node_pullup: netlist.node_initial_pullup.clone(),
+            node_pulldown: BitSet::new(node_count),
+            transistor_on: BitSet::new(transistor_count),
+            group: NodeGroup::new(&netlist),
+            netlist,
             step_cycle_count: 0,
             prev_ppu_ale: false,
             prev_ppu_read: true,
             prev_ppu_write: true,
             chr_address: 0,
-            mirroring_type: MirroringType::Horizontal,
-            chr_ram: Box::new([0; 0x2000]),
-            nametable_ram: Box::new([[0; 0x400]; 4]),
-            cpu_ram: Box::new([0; 0x800]),
-            prg_ram: Box::new([0; 0x8000]),
-            last_cpu_db_value: 0,
+            memory: Memory::new(),
             last_data: 0,
             prev_hpos: -1,
             ppu_framebuffer: Box::new([0; 256 * 240]),
-            sprite_nodes,
-            palette_nodes,
+            raw_framebuffer: Box::new([0; 256 * 240]),
+            output_mode: OutputMode::Argb,
+            frame_counter: FrameCounter::new(),
+            palette,
+            subcarrier_phase: 0,
+            composite: None,
+            event_log: None,
             recalc_swap_list: RecalcSwapList::new(),
+            power_on: PowerOnOptions::default(),
+            clock_alignment: 0,
+            reset_hold_half_steps: DEFAULT_RESET_HOLD_HALF_STEPS,
+            hand_off: None,
+            #[cfg(feature = "parallel")]
+            parallel_recalc: None,
+            #[cfg(feature = "compiled")]
+            compiled_tables: true,
+            #[cfg(feature = "stats")]
+            stats: SimulationStats::new(node_count),
+        }
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> &SimulationStats {
+        &self.stats
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn stats_mut(&mut self) -> &mut SimulationStats {
+        &mut self.stats
+    }
+
+    /// The netlist's static channel-connected components, for tools that analyse the netlist.
+    pub fn channel_components(&self) -> &ChannelComponents {
+        &self.netlist.components
+    }
+
+    /// Press the console's reset button, holding it for the reset hold time.
+    ///
+    /// As on the console, the CPU and PPU start over from their reset state while CPU RAM, VRAM,
+    /// palette, OAM and the cartridge keep their contents. The clock keeps running while reset is
+    /// held, so the simulation advances by the hold time.
+    pub fn reset(&mut self) {
+        self.init(true);
+    }
+
+    /// Turn the console off and on again with the same cartridge inserted, clearing everything
+    /// else.
+    pub fn power_cycle(&mut self) {
+        self.init(false);
+    }
+
+    /// How many half-steps `reset` and `power_cycle` hold the reset line low for. Defaults to 192,
+    /// eight CPU cycles.
+    pub fn reset_hold_half_steps(&self) -> u32 {
+        self.reset_hold_half_steps
+    }
+
+    pub fn set_reset_hold_half_steps(&mut self, half_steps: u32) {
+        self.reset_hold_half_steps = half_steps;
+    }
+
+    /// The pixels output so far, as 256x240 ARGB colours in rows from the top.
+    pub fn framebuffer(&self) -> &[u32] {
+        &self.ppu_framebuffer[..]
+    }
+
+    /// The pixels output so far as 9-bit values, the palette index with the emphasis bits above
+    /// it, when the output mode includes them.
+    pub fn raw_framebuffer(&self) -> &[u16] {
+        &self.raw_framebuffer[..]
+    }
+
+    pub fn output_mode(&self) -> OutputMode {
+        self.output_mode
+    }
+
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.output_mode = mode;
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Set the colours pixels are output in from now on.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// The composite video signal output so far, if `set_composite_output` turned it on.
+    pub fn composite_frame(&self) -> Option<&CompositeFrame> {
+        self.composite.as_deref()
+    }
+
+    /// Turn on or off synthesising the composite signal the PPU outputs for each dot, alongside
+    /// the framebuffers. It's slower than looking colours up in the palette but shows the
+    /// artifacts an NTSC television would when it's decoded.
+    pub fn set_composite_output(&mut self, enabled: bool) {
+        if enabled != self.composite.is_some() {
+            self.composite = enabled.then(|| Box::new(CompositeFrame::new()));
         }
     }
 
+    /// Save the framebuffer as a PNG. Called when `is_frame_completed` it saves a whole frame,
+    /// and otherwise the current frame so far over the rest of the last one.
+    pub fn save_frame_png<P: AsRef<Path>>(&self, path: P, crop: Crop) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write_frame_png(&mut file, self.framebuffer(), crop)?;
+        file.flush()
+    }
+
+    /// Save the framebuffer as a binary PPM, like `save_frame_png`.
+    pub fn save_frame_ppm<P: AsRef<Path>>(&self, path: P, crop: Crop) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        write_frame_ppm(&mut file, self.framebuffer(), crop)?;
+        file.flush()
+    }
+
+    /// The number of frames the PPU has finished outputting since power-on.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_counter.count()
+    }
+
+    /// Whether the last `half_step` finished a frame, leaving a complete picture in the
+    /// framebuffer.
+    pub fn is_frame_completed(&self) -> bool {
+        self.frame_counter.is_completed()
+    }
+
+    pub fn power_on_options(&self) -> PowerOnOptions {
+        self.power_on
+    }
+
+    /// Set the state the console is in the next time it's powered on.
+    pub fn set_power_on_options(&mut self, options: PowerOnOptions) {
+        self.power_on = options;
+    }
+
+    /// The clock alignment the console was last powered on in.
+    pub fn clock_alignment(&self) -> u8 {
+        self.clock_alignment
+    }
+
     pub fn load_rom<R: Read + Seek>(&mut self, input: &mut R) {
         use nes_rom_loader::{Mirroring, NesRom};
         // TODO: Return Result so failure can be handled gracefully
@@ -141,7 +334,7 @@ impl SimulationState {
             _ => unimplemented!(),
         };
 
-        self.mirroring_type = mirroring_type;
+        self.memory.mirroring_type = mirroring_type;
 
         let mut prg = rom.prg.clone();
 
@@ -156,13 +349,13 @@ impl SimulationState {
 
     fn set_memory_state(&mut self, memory_type: MemoryType, buffer: &[u8]) {
         match memory_type {
-            MemoryType::PrgRam => self.prg_ram.copy_from_slice(buffer),
-            MemoryType::ChrRam => self.chr_ram.copy_from_slice(buffer),
-            MemoryType::CpuRam => self.cpu_ram.copy_from_slice(buffer),
+            MemoryType::PrgRam => self.memory.prg_ram.copy_from_slice(buffer),
+            MemoryType::ChrRam => self.memory.chr_ram.copy_from_slice(buffer),
+            MemoryType::CpuRam => self.memory.cpu_ram.copy_from_slice(buffer),
             MemoryType::NametableRam => {
                 for i in 0..4 {
                     let start_index = i * 0x400;
-                    self.nametable_ram[i]
+                    self.memory.nametable_ram[i]
                         .copy_from_slice(&buffer[start_index..(start_index + 0x400)]);
                 }
             }
@@ -182,7 +375,7 @@ impl SimulationState {
 
     fn palette_write(&mut self, addr: u16, val: u8) {
         for b in 0..6 {
-            let (n0, n1) = self.palette_nodes[addr as usize][b as usize];
+            let (n0, n1) = self.netlist.palette_nodes[addr as usize][b as usize];
 
             if val & (1 << b) > 0 {
                 self.set_bit(n1, n0);
@@ -194,7 +387,7 @@ impl SimulationState {
 
     fn sprite_write(&mut self, addr: u16, val: u8) {
         for b in 0..8 {
-            let (n0, n1) = self.sprite_nodes[addr as usize][b as usize];
+            let (n0, n1) = self.netlist.sprite_nodes[addr as usize][b as usize];
             if val & (1 << b) > 0 {
                 self.set_bit(n1, n0);
             } else {
@@ -203,58 +396,73 @@ impl SimulationState {
         }
     }
 
+    /// Read a palette entry back out of the cells `palette_write` sets.
+    fn palette_read(&self, addr: u16) -> u8 {
+        self.read_cells(&self.netlist.palette_nodes[addr as usize])
+    }
+
+    /// Read an OAM byte back out of the cells `sprite_write` sets.
+    fn sprite_read(&self, addr: u16) -> u8 {
+        self.read_cells(&self.netlist.sprite_nodes[addr as usize])
+    }
+
+    /// The bits stored in cells of two nodes, high in the second for a 1. Bits without cells read
+    /// as 0.
+    fn read_cells(&self, cells: &[(i32, i32)]) -> u8 {
+        let mut res = 0;
+        for (b, (_, n1)) in cells.iter().enumerate() {
+            if *n1 >= 0 && self.is_node_high(*n1 as u16) {
+                res |= 1 << b;
+            }
+        }
+        res
+    }
+
     fn set_bit(&mut self, n1: i32, n2: i32) {
         if n1 < 0 || n2 < 0 {
             return;
         }
 
-        for gate in &self.nodes[n1 as usize].gates {
-            self.transistors[*gate as usize].on.set(true);
+        for gate in self.netlist.gates(n1 as usize) {
+            self.transistor_on.set(*gate as usize);
         }
 
-        for gate in &self.nodes[n2 as usize].gates {
-            self.transistors[*gate as usize].on.set(false);
+        for gate in self.netlist.gates(n2 as usize) {
+            self.transistor_on.clear(*gate as usize);
         }
 
-        self.nodes[n1 as usize].state.set(true);
-        self.nodes[n2 as usize].state.set(false);
+        self.node_state.set(n1 as usize);
+        self.node_state.clear(n2 as usize);
         self.recalc_node_list(&[n1 as u16, n2 as u16]);
     }
 
     fn init(&mut self, soft_reset: bool) {
-        self.prev_hpos = -1;
-
         if soft_reset {
             self.set_low(NODE_RESET);
-            for _ in 0..=(12 * 8 * 2) {
-                if self.is_node_high(NODE_CLK0) {
-                    self.set_low(NODE_CLK0);
-                } else {
-                    self.set_high(NODE_CLK0);
-                }
+            for _ in 0..self.reset_hold_half_steps {
+                self.half_step();
             }
             self.set_high(NODE_RESET);
         } else {
+            self.prev_hpos = -1;
             self.ppu_framebuffer.iter_mut().for_each(|b| *b = 0);
-            self.cpu_ram.iter_mut().for_each(|b| *b = 0);
-            self.prg_ram.iter_mut().for_each(|b| *b = 0);
-            self.chr_ram.iter_mut().for_each(|b| *b = 0);
-            self.nametable_ram
-                .iter_mut()
-                .for_each(|nt| nt.iter_mut().for_each(|b| *b = 0));
-
-            for node in self.nodes.iter() {
-                node.state.set(false);
-                node.floating.set(true);
+            self.raw_framebuffer.iter_mut().for_each(|b| *b = 0);
+            self.frame_counter = FrameCounter::new();
+            self.subcarrier_phase = 0;
+            if self.composite.is_some() {
+                self.composite = Some(Box::new(CompositeFrame::new()));
             }
+            self.memory.power_on(&self.power_on);
+
+            self.node_state.clear_all();
 
-            self.nodes[NODE_GND as usize].state.set(false);
-            self.nodes[NODE_GND as usize].floating.set(false);
-            self.nodes[NODE_PWR as usize].state.set(true);
-            self.nodes[NODE_PWR as usize].floating.set(false);
+            self.node_state.clear(NODE_GND as usize);
+            self.node_state.set(NODE_PWR as usize);
 
-            for (i, on) in self.transistors_initial_power_state.iter().enumerate() {
-                self.transistors[i].on.set(*on);
+            self.transistor_on = self.netlist.transistors_initial_power_state.clone();
+            let mut rng = self.power_on.random_undriven_nodes.map(Rng::new);
+            if let Some(rng) = &mut rng {
+                self.randomise_undriven_nodes(rng);
             }
 
             self.set_low(NODE_RESET);
@@ -271,23 +479,48 @@ impl SimulationState {
             self.set_high(NODE_CPU_IRQ);
             self.set_high(NODE_CPU_NMI);
 
-            self.recalc_node_list(&self.all_recalc_nodes.clone());
+            let netlist = Arc::clone(&self.netlist);
+            self.recalc_node_list(&netlist.all_recalc_nodes);
+            if let Some(rng) = &mut rng {
+                self.randomise_cells(rng);
+            }
+
+            self.clock_alignment = self
+                .power_on
+                .clock_alignment
+                .resolve(self.netlist.region.clock_alignments());
+            if self.clock_alignment > 0 {
+                // Hold the PPU's copy of the clock still while the CPU's divider runs on, delaying
+                // the PPU by a master clock for each clock it misses.
+                self.set_low(NODE_NOT_CLK0);
+                for _ in 0..self.clock_alignment {
+                    self.set_high(NODE_CLK0);
+                    self.set_low(NODE_CLK0);
+                }
+                self.release(NODE_NOT_CLK0);
+            }
 
-            for _ in 0..(12 * 8) {
+            for _ in 0..(self.reset_hold_half_steps / 2) {
                 self.set_high(NODE_CLK0);
                 self.set_low(NODE_CLK0);
             }
 
             self.set_high(NODE_RESET);
-        }
 
-        self.chr_address = 0;
-        self.prev_ppu_read = true;
-        self.prev_ppu_write = true;
-        self.prev_ppu_ale = false;
+            self.step_cycle_count = 0;
+            self.chr_address = 0;
+            self.prev_ppu_read = true;
+            self.prev_ppu_write = true;
+            self.prev_ppu_ale = false;
+        }
     }
 
     pub fn half_step(&mut self) {
+        #[cfg(feature = "stats")]
+        self.stats.begin_half_step();
+
+        self.frame_counter.begin_half_step();
+        self.subcarrier_phase = (self.subcarrier_phase + 1) % SUBCARRIER_PHASES;
         let cpu_clk0 = self.is_node_high(NODE_CPU_CLK0);
         let clk = self.is_node_high(NODE_CLK0);
 
@@ -309,7 +542,7 @@ impl SimulationState {
         {
             // Simulate the 74139's logic
             self.set_low(NODE_IO_CE);
-            self.step_cycle_count = 11;
+            self.step_cycle_count = self.netlist.region.io_ce_low_half_steps();
         }
 
         self.handle_chr_bus();
@@ -326,25 +559,39 @@ impl SimulationState {
             let hpos = i32::from(self.read_hpos()) - 2;
             if hpos != self.prev_hpos {
                 let vpos = self.read_vpos();
-                if hpos >= 0 && hpos < 256 && vpos < 240 {
-                    let palette_entry = self.read_bit(NODE_PAL_D0_OUT)
-                        | (self.read_bit(NODE_PAL_D1_OUT) << 1)
-                        | (self.read_bit(NODE_PAL_D2_OUT) << 2)
-                        | (self.read_bit(NODE_PAL_D3_OUT) << 3)
-                        | (self.read_bit(NODE_PAL_D4_OUT) << 4)
-                        | (self.read_bit(NODE_PAL_D5_OUT) << 5);
-                    self.ppu_framebuffer[((vpos << 8) | (hpos as u16)) as usize] =
-                        PALETTE_ARGB[palette_entry as usize];
+                self.frame_counter.update(vpos);
+                if (0..256).contains(&hpos) && vpos < VISIBLE_SCANLINES {
+                    let pixel = self.read_pixel();
+                    let index = ((vpos << 8) | (hpos as u16)) as usize;
+                    if self.output_mode.has_argb() {
+                        self.ppu_framebuffer[index] = self.palette.argb(pixel);
+                    }
+                    if self.output_mode.has_raw_pixels() {
+                        self.raw_framebuffer[index] = pixel;
+                    }
+                    if let Some(composite) = &mut self.composite {
+                        composite.write_dot(index, pixel, self.subcarrier_phase);
+                    }
                 }
                 self.prev_hpos = hpos;
             }
         }
+
+        self.log_half_step_events();
+
+        #[cfg(feature = "stats")]
+        self.stats.end_half_step();
     }
 
     fn handle_cpu_bus_read(&mut self) {
         if self.is_node_high(NODE_CPU_RW) {
             let a = self.read_cpu_address_bus();
-            let (d, open_bus) = self.cpu_read(a);
+            if let Some(d) = self.hand_off_read(a) {
+                self.write_cpu_db(d);
+                return;
+            }
+
+            let (d, open_bus) = self.memory.cpu_read(a);
 
             if open_bus {
                 self.float_cpu_db();
@@ -358,33 +605,14 @@ impl SimulationState {
         if !self.is_node_high(NODE_CPU_RW) {
             let a = self.read_cpu_address_bus();
             let d = self.read_cpu_data_bus();
-            self.cpu_write(a, d);
+            let d = self.hand_off_write(a, d);
+            self.memory.cpu_write(a, d);
         }
     }
 
     fn read_cpu_address_bus(&mut self) -> u16 {
         let mut res = 0_u16;
-        for (i, nn) in [
-            NODE_CPU_AB0,
-            NODE_CPU_AB1,
-            NODE_CPU_AB2,
-            NODE_CPU_AB3,
-            NODE_CPU_AB4,
-            NODE_CPU_AB5,
-            NODE_CPU_AB6,
-            NODE_CPU_AB7,
-            NODE_CPU_AB8,
-            NODE_CPU_AB9,
-            NODE_CPU_AB10,
-            NODE_CPU_AB11,
-            NODE_CPU_AB12,
-            NODE_CPU_AB13,
-            NODE_CPU_AB14,
-            NODE_CPU_AB15,
-        ]
-        .iter()
-        .enumerate()
-        {
+        for (i, nn) in CPU_AB_NODES.iter().enumerate() {
             res += (self.is_node_high(*nn) as u16) << i;
         }
         res
@@ -392,26 +620,14 @@ impl SimulationState {
 
     fn read_cpu_data_bus(&mut self) -> u8 {
         let mut res = 0_u8;
-        for (i, nn) in [
-            NODE_CPU_DB0,
-            NODE_CPU_DB1,
-            NODE_CPU_DB2,
-            NODE_CPU_DB3,
-            NODE_CPU_DB4,
-            NODE_CPU_DB5,
-            NODE_CPU_DB6,
-            NODE_CPU_DB7,
-        ]
-        .iter()
-        .enumerate()
-        {
+        for (i, nn) in CPU_DB_NODES.iter().enumerate() {
             res += (self.is_node_high(*nn) as u8) << i;
         }
         res
     }
 
     fn is_node_high(&self, node_number: u16) -> bool {
-        self.nodes[node_number as usize].state.get()
+        self.node_state.get(node_number as usize)
     }
 
     fn recalc_node_list(&mut self, recalc_list: &[u16]) {
@@ -420,87 +636,169 @@ impl SimulationState {
             if iter_count >= 99 {
                 panic!("iter count exceeded");
             }
-            for node_number in self.recalc_swap_list.cur_list().borrow().iter() {
-                let node_number = *node_number;
-                if node_number == NODE_GND || node_number == NODE_PWR {
-                    continue;
+            #[cfg(feature = "stats")]
+            self.stats.record_iteration();
+
+            #[cfg(feature = "parallel")]
+            {
+                if self.should_recalc_in_parallel() {
+                    self.recalc_cur_list_in_parallel();
                 } else {
-                    self.get_node_group(node_number);
-                    let new_state = self.get_node_value();
-                    for node_number in &self.group {
-                        let node_number = *node_number as usize;
-                        if self.nodes[node_number].state.get() != new_state {
-                            self.nodes[node_number].state.set(new_state);
-                            for i in &self.nodes[node_number].gates {
-                                if self.nodes[node_number].state.get() {
-                                    self.turn_transistor_on(*i);
-                                } else {
-                                    self.turn_transistor_off(*i);
-                                }
-                            }
-                        }
-                    }
+                    self.recalc_cur_list();
                 }
             }
+            #[cfg(not(feature = "parallel"))]
+            self.recalc_cur_list();
 
             if self.recalc_swap_list.is_next_list_empty() {
                 return;
             }
 
-            self.processed_nodes
-                .clear(&self.recalc_swap_list.next_list());
             self.recalc_swap_list.swap();
         }
     }
 
-    fn turn_transistor_on(&self, i: u16) {
-        let i = i as usize;
-        if !self.transistors[i].on.get() {
-            self.transistors[i].on.set(true);
-            self.add_recalc_node(self.transistors[i].c1);
+    fn recalc_cur_list(&mut self) {
+        for i in 0..self.recalc_swap_list.cur_len() {
+            let node_number = self.recalc_swap_list.cur_node(i);
+            if node_number != NODE_GND && node_number != NODE_PWR {
+                self.recalc_node(node_number);
+            }
         }
     }
 
-    fn turn_transistor_off(&self, i: u16) {
-        let i = i as usize;
-        if self.transistors[i].on.get() {
-            self.transistors[i].on.set(false);
-            self.add_recalc_node(self.transistors[i].c1);
-            self.add_recalc_node(self.transistors[i].c2);
+    fn recalc_node(&mut self, node_number: u16) {
+        let new_state = self.resolve_group(node_number);
+        self.set_group_state(new_state);
+    }
+
+    /// Find the group containing `node_number` and the value it settles to.
+    #[cfg(not(feature = "compiled"))]
+    fn resolve_group(&mut self, node_number: u16) -> bool {
+        self.interpret_group(node_number)
+    }
+
+    fn interpret_group(&mut self, node_number: u16) -> bool {
+        self.group
+            .collect(&self.netlist, &self.transistor_on, node_number);
+        self.group.value(
+            &self.netlist,
+            &self.node_state,
+            &self.node_pullup,
+            &self.node_pulldown,
+        )
+    }
+
+    /// Move every node of the current group to `new_state`, switching the transistors they gate and
+    /// queueing the nodes affected by those transistors for the next iteration.
+    fn set_group_state(&mut self, new_state: bool) {
+        // Groups always start with the node they were resolved from.
+        #[cfg(feature = "stats")]
+        self.stats
+            .record_recalc(self.group.nodes[0], self.group.nodes.len());
+
+        for node_number in &self.group.nodes {
+            let node_number = *node_number as usize;
+            if self.node_state.get(node_number) != new_state {
+                self.node_state.assign(node_number, new_state);
+                for i in self.netlist.gates(node_number) {
+                    let i = *i as usize;
+                    if self.transistor_on.get(i) != new_state {
+                        // Turning a transistor on can only merge groups, so only one side needs
+                        // to be revisited. Turning it off may split them and needs both.
+                        self.transistor_on.assign(i, new_state);
+                        #[cfg(feature = "stats")]
+                        self.stats.record_transistor_toggle();
+                        let transistor = &self.netlist.transistors[i];
+                        self.recalc_swap_list.push_next_list(transistor.c1);
+                        if !new_state {
+                            self.recalc_swap_list.push_next_list(transistor.c2);
+                        }
+                    }
+                }
+            }
         }
     }
 
-    fn add_recalc_node(&self, node_number: u16) {
-        if node_number == NODE_GND || node_number == NODE_PWR {
-            return;
+    fn set_high(&mut self, node_number: u16) {
+        self.node_pullup.set(node_number as usize);
+        self.node_pulldown.clear(node_number as usize);
+        self.recalc_node_list(&[node_number])
+    }
+
+    fn set_low(&mut self, node_number: u16) {
+        self.node_pullup.clear(node_number as usize);
+        self.node_pulldown.set(node_number as usize);
+        self.recalc_node_list(&[node_number])
+    }
+
+    /// Put every node nothing drives at power-on in a random state: all but power, ground, the
+    /// nodes with pull-ups and the inputs `init` drives. Unlike the rest of the chip, the latches
+    /// inside it don't settle into the same state every time, and the first recalculation of the
+    /// whole chip settles them from these states.
+    ///
+    /// The transistors each node gates are switched to match, so the chip starts out consistent.
+    fn randomise_undriven_nodes(&mut self, rng: &mut Rng) {
+        const DRIVEN_AT_POWER_ON: [u16; 6] = [
+            NODE_RESET,
+            NODE_CLK0,
+            NODE_IO_CE,
+            NODE_INT,
+            NODE_CPU_SO,
+            NODE_CPU_IRQ,
+        ];
+
+        let netlist = Arc::clone(&self.netlist);
+        for node_number in &netlist.all_recalc_nodes {
+            let high = rng.next_u64() & 1 == 1;
+            if !high
+                || netlist.node_initial_pullup.get(*node_number as usize)
+                || DRIVEN_AT_POWER_ON.contains(node_number)
+            {
+                continue;
+            }
+            self.node_state.set(*node_number as usize);
+            for gate in netlist.gates(*node_number as usize) {
+                self.transistor_on.set(*gate as usize);
+            }
         }
+    }
 
-        if !self.processed_nodes.contains(node_number) {
-            self.recalc_swap_list.push_next_list(node_number);
-            self.processed_nodes.set(node_number);
+    /// Fill the palette and OAM cells with random bytes. The random word lines the first
+    /// recalculation settles into connect most cells to their bit lines and overwrite them, so
+    /// this is done after it.
+    fn randomise_cells(&mut self, rng: &mut Rng) {
+        for addr in 0..self.netlist.palette_nodes.len() {
+            self.palette_write(addr as u16, rng.next_u64() as u8);
+        }
+        for addr in 0..self.netlist.sprite_nodes.len() {
+            self.sprite_write(addr as u16, rng.next_u64() as u8);
         }
     }
 
-    fn set_high(&mut self, node_number: u16) {
-        self.nodes[node_number as usize].pullup.set(true);
-        self.nodes[node_number as usize].pulldown.set(false);
+    /// Stop driving a node set with `set_high` or `set_low`, leaving it to the netlist.
+    fn release(&mut self, node_number: u16) {
+        let pullup = self.netlist.node_initial_pullup.get(node_number as usize);
+        self.node_pullup.assign(node_number as usize, pullup);
+        self.node_pulldown.clear(node_number as usize);
         self.recalc_node_list(&[node_number])
     }
 
-    fn set_low(&mut self, node_number: u16) {
-        self.nodes[node_number as usize].pullup.set(false);
-        self.nodes[node_number as usize].pulldown.set(true);
-        self.recalc_node_list(&[node_number])
+    /// The 9-bit pixel the PPU is outputting: the palette index with the emphasis bits above it.
+    fn read_pixel(&self) -> u16 {
+        let mut pixel = 0;
+        for (i, node_number) in PAL_D_OUT_NODES.iter().enumerate() {
+            pixel |= u16::from(self.read_bit(*node_number)) << i;
+        }
+        for (i, node_number) in NOT_EMPH_OUT_NODES.iter().enumerate() {
+            pixel |= u16::from(!self.is_node_high(*node_number)) << (6 + i);
+        }
+        pixel
     }
 
     fn read_db(&self) -> u8 {
         let mut res = 0_u8;
-        for (i, node_number) in [
-            NODE_DB0, NODE_DB1, NODE_DB2, NODE_DB3, NODE_DB4, NODE_DB5, NODE_DB6, NODE_DB7,
-        ]
-        .iter()
-        .enumerate()
-        {
+        for (i, node_number) in DB_NODES.iter().enumerate() {
             let node_number = *node_number;
             res += (self.is_node_high(node_number) as u8) << i;
         }
@@ -509,13 +807,7 @@ impl SimulationState {
 
     fn read_hpos(&self) -> u16 {
         let mut res = 0_u16;
-        for (i, node_number) in [
-            NODE_HPOS0, NODE_HPOS1, NODE_HPOS2, NODE_HPOS3, NODE_HPOS4, NODE_HPOS5, NODE_HPOS6,
-            NODE_HPOS7, NODE_HPOS8,
-        ]
-        .iter()
-        .enumerate()
-        {
+        for (i, node_number) in HPOS_NODES.iter().enumerate() {
             let node_number = *node_number;
             res += (self.is_node_high(node_number) as u16) << i;
         }
@@ -524,13 +816,7 @@ impl SimulationState {
 
     fn read_vpos(&self) -> u16 {
         let mut res = 0_u16;
-        for (i, node_number) in [
-            NODE_VPOS0, NODE_VPOS1, NODE_VPOS2, NODE_VPOS3, NODE_VPOS4, NODE_VPOS5, NODE_VPOS6,
-            NODE_VPOS7, NODE_VPOS8,
-        ]
-        .iter()
-        .enumerate()
-        {
+        for (i, node_number) in VPOS_NODES.iter().enumerate() {
             let node_number = *node_number;
             res += (self.is_node_high(node_number) as u16) << i;
         }
@@ -539,13 +825,7 @@ impl SimulationState {
 
     fn read_ab(&self) -> u16 {
         let mut res = 0_u16;
-        for (i, node_number) in [
-            NODE_AB0, NODE_AB1, NODE_AB2, NODE_AB3, NODE_AB4, NODE_AB5, NODE_AB6, NODE_AB7,
-            NODE_AB8, NODE_AB9, NODE_AB10, NODE_AB11, NODE_AB12, NODE_AB13,
-        ]
-        .iter()
-        .enumerate()
-        {
+        for (i, node_number) in AB_NODES.iter().enumerate() {
             let node_number = *node_number;
             res += (self.is_node_high(node_number) as u16) << i;
         }
@@ -569,7 +849,13 @@ impl SimulationState {
 
         // falling edge of /RD - put bits on bus
         if self.prev_ppu_read && !rd {
-            self.write_db(self.ppu_read(self.chr_address));
+            let data = self.memory.ppu_read(self.chr_address);
+            self.write_db(data);
+            self.log_event(PpuEventKind::MemoryAccess {
+                addr: self.chr_address,
+                data,
+                write: false,
+            });
         }
 
         // rising edge of /RD - flaot the data bus
@@ -580,7 +866,12 @@ impl SimulationState {
         // rising edge of /WR - store data in RAM
         if !self.prev_ppu_write && wr {
             let ppu_data_bus_val = self.read_ppu_data_bus();
-            self.ppu_write(self.chr_address, ppu_data_bus_val);
+            self.memory.ppu_write(self.chr_address, ppu_data_bus_val);
+            self.log_event(PpuEventKind::MemoryAccess {
+                addr: self.chr_address,
+                data: ppu_data_bus_val,
+                write: true,
+            });
         }
 
         self.read_ppu_data_bus();
@@ -600,230 +891,46 @@ impl SimulationState {
         let mut recalc_nodes = [0_u16; 8];
         for (i, node_number) in nodes.iter().enumerate() {
             let node_number = *node_number;
-            self.nodes[node_number as usize].pulldown.set(false);
-            self.nodes[node_number as usize].pullup.set(false);
-            recalc_nodes[i as usize] = node_number;
+            self.node_pulldown.clear(node_number as usize);
+            self.node_pullup.clear(node_number as usize);
+            recalc_nodes[i] = node_number;
         }
         self.recalc_node_list(&recalc_nodes);
     }
 
     fn float_db(&mut self) {
-        self.float_byte([
-            NODE_DB0, NODE_DB1, NODE_DB2, NODE_DB3, NODE_DB4, NODE_DB5, NODE_DB6, NODE_DB7,
-        ])
+        self.float_byte(DB_NODES)
     }
 
     fn float_cpu_db(&mut self) {
-        self.float_byte([
-            NODE_CPU_DB0,
-            NODE_CPU_DB1,
-            NODE_CPU_DB2,
-            NODE_CPU_DB3,
-            NODE_CPU_DB4,
-            NODE_CPU_DB5,
-            NODE_CPU_DB6,
-            NODE_CPU_DB7,
-        ])
-    }
-
-    /// Read byte at address in memory, returning the byte at that address and a boolean
-    /// indicating an open bus.
-    fn cpu_read(&self, a: u16) -> (u8, bool) {
-        if a < 0x2000 {
-            (self.cpu_ram[(a & 0x7ff) as usize], false)
-        } else if a >= 0x8000 {
-            (self.prg_ram[(a - 0x8000) as usize], false)
-        } else {
-            // TODO: proper open bus implementation
-            (self.last_cpu_db_value, true)
-        }
-    }
-
-    fn cpu_write(&mut self, a: u16, d: u8) {
-        if a < 0x2000 {
-            self.cpu_ram[(a & 0x7ff) as usize] = d;
-        } else if a >= 0x8000 {
-            self.prg_ram[(a - 0x8000) as usize] = d;
-        }
-        // else external device (i.e. PPU)
-    }
-
-    fn ppu_write(&mut self, mut a: u16, d: u8) {
-        a &= 0x3fff;
-        if a >= 0x3000 {
-            a -= 0x1000;
-        }
-
-        if a < 0x2000 {
-            self.chr_ram[a as usize] = d
-        } else {
-            self.nametable_ram[self.get_nametable(a) as usize][(a & 0x3ff) as usize] = d;
-        }
-    }
-
-    fn ppu_read(&self, mut a: u16) -> u8 {
-        a &= 0x3fff;
-        if a >= 0x3000 {
-            a -= 0x1000;
-        }
-
-        if a < 0x2000 {
-            self.chr_ram[a as usize]
-        } else {
-            self.nametable_ram[self.get_nametable(a) as usize][(a & 0x3ff) as usize]
-        }
-    }
-
-    fn get_nametable(&self, a: u16) -> u16 {
-        match self.mirroring_type {
-            MirroringType::Horizontal => {
-                if a & 0x800 > 0 {
-                    1
-                } else {
-                    0
-                }
-            }
-            MirroringType::Vertical => {
-                if a & 0x400 > 0 {
-                    1
-                } else {
-                    0
-                }
-            }
-            MirroringType::FourScreens => {
-                // TODO: Wouldn't this always equal 0?
-                // (a & 0xc00) >> 16
-                unimplemented!()
-            }
-            MirroringType::ScreenAOnly => 0,
-            MirroringType::ScreenBOnly => 1,
-        }
+        self.float_byte(CPU_DB_NODES)
     }
 
     fn write_cpu_db(&mut self, val: u8) {
-        self.write_byte(
-            [
-                NODE_CPU_DB0,
-                NODE_CPU_DB1,
-                NODE_CPU_DB2,
-                NODE_CPU_DB3,
-                NODE_CPU_DB4,
-                NODE_CPU_DB5,
-                NODE_CPU_DB6,
-                NODE_CPU_DB7,
-            ],
-            val,
-        );
+        self.write_byte(CPU_DB_NODES, val);
     }
 
     fn write_db(&mut self, val: u8) {
-        self.write_byte(
-            [
-                NODE_DB0, NODE_DB1, NODE_DB2, NODE_DB3, NODE_DB4, NODE_DB5, NODE_DB6, NODE_DB7,
-            ],
-            val,
-        );
+        self.write_byte(DB_NODES, val);
     }
 
     fn write_byte(&mut self, nodes: [u16; 8], mut val: u8) {
         let mut recalc_nodes = [0_u16; 8];
         for (i, node_number) in nodes.iter().enumerate() {
             let node_number = *node_number;
-            if val % 2 == 0 {
-                self.nodes[node_number as usize].pulldown.set(true);
-                self.nodes[node_number as usize].pullup.set(false);
+            if val & 1 == 0 {
+                self.node_pulldown.set(node_number as usize);
+                self.node_pullup.clear(node_number as usize);
             } else {
-                self.nodes[node_number as usize].pulldown.set(false);
-                self.nodes[node_number as usize].pullup.set(true);
+                self.node_pulldown.clear(node_number as usize);
+                self.node_pullup.set(node_number as usize);
             }
-            recalc_nodes[i as usize] = node_number;
+            recalc_nodes[i] = node_number;
             val >>= 1;
         }
 
         self.recalc_node_list(&recalc_nodes);
     }
-
-    fn get_node_value(&mut self) -> bool {
-        if self.has_ground && self.has_power {
-            for i in &self.group {
-                let i = *i;
-                if i == 359
-                    || i == 566
-                    || i == 691
-                    || i == 871
-                    || i == 870
-                    || i == 864
-                    || i == 856
-                    || i == 818
-                {
-                    self.has_ground = false;
-                    self.has_power = false;
-                    break;
-                }
-            }
-        }
-
-        if self.has_ground {
-            false
-        } else if self.has_power {
-            true
-        } else {
-            let mut hi_area = 0_u64;
-            let mut lo_area = 0_u64;
-            for node_number in &self.group {
-                let node = &self.nodes[*node_number as usize];
-                if node.pullup.get() {
-                    return true;
-                } else if node.pulldown.get() {
-                    return false;
-                } else if node.state.get() {
-                    hi_area += node.area
-                } else {
-                    lo_area += node.area
-                }
-            }
-
-            hi_area > lo_area
-        }
-    }
-
-    fn get_node_group(&mut self, node_number: u16) {
-        self.has_ground = false;
-        self.has_power = false;
-        self.group.clear();
-        self.add_node_to_group(node_number);
-    }
-
-    fn add_node_to_group(&mut self, node_number: u16) {
-        if node_number == NODE_GND {
-            self.has_ground = true;
-            return;
-        }
-
-        if node_number == NODE_PWR {
-            self.has_power = true;
-            return;
-        }
-
-        if self.group.contains(&node_number) {
-            return;
-        }
-
-        self.group.push(node_number);
-
-        for i in 0..(self.node_counts[node_number as usize] as usize) {
-            let transistor_index = self.nodes_c1_c2[node_number as usize][i] as usize;
-            let transistor = &self.transistors[transistor_index];
-            if transistor.on.get() {
-                let node_to_add = if transistor.c1 == node_number {
-                    transistor.c2
-                } else {
-                    transistor.c1
-                };
-                self.add_node_to_group(node_to_add);
-            }
-        }
-    }
 }
 
 impl Default for SimulationState {
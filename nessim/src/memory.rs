@@ -0,0 +1,127 @@
+use crate::power_on::PowerOnOptions;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum MirroringType {
+    Horizontal,
+    Vertical,
+    FourScreens,
+    ScreenAOnly,
+    ScreenBOnly,
+}
+
+/// The RAM and ROM attached to the CPU and PPU buses, along with the cartridge's address decoding.
+#[derive(Clone)]
+pub struct Memory {
+    pub mirroring_type: MirroringType,
+    pub chr_ram: Box<[u8; 0x2000]>,
+    pub nametable_ram: Box<[[u8; 0x400]; 4]>,
+    pub cpu_ram: Box<[u8; 0x800]>,
+    pub prg_ram: Box<[u8; 0x8000]>,
+    /// When set, the CPU sees this in place of the console's memory map.
+    pub flat_cpu_ram: Option<Box<[u8; 0x10000]>>,
+    pub last_cpu_db_value: u8,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory {
+            mirroring_type: MirroringType::Horizontal,
+            chr_ram: Box::new([0; 0x2000]),
+            nametable_ram: Box::new([[0; 0x400]; 4]),
+            cpu_ram: Box::new([0; 0x800]),
+            prg_ram: Box::new([0; 0x8000]),
+            flat_cpu_ram: None,
+            last_cpu_db_value: 0,
+        }
+    }
+
+    /// Fill the console's RAMs with the contents they power on with. The cartridge keeps its own.
+    pub fn power_on(&mut self, options: &PowerOnOptions) {
+        options.cpu_ram.fill(self.cpu_ram.iter_mut());
+        if let Some(ram) = &mut self.flat_cpu_ram {
+            options.cpu_ram.fill(ram.iter_mut());
+        }
+        options
+            .nametable_ram
+            .fill(self.nametable_ram.iter_mut().flat_map(|nt| nt.iter_mut()));
+    }
+
+    /// Read byte at address in memory, returning the byte at that address and a boolean
+    /// indicating an open bus.
+    pub fn cpu_read(&self, a: u16) -> (u8, bool) {
+        if let Some(ram) = &self.flat_cpu_ram {
+            (ram[a as usize], false)
+        } else if a < 0x2000 {
+            (self.cpu_ram[(a & 0x7ff) as usize], false)
+        } else if a >= 0x8000 {
+            (self.prg_ram[(a - 0x8000) as usize], false)
+        } else {
+            // TODO: proper open bus implementation
+            (self.last_cpu_db_value, true)
+        }
+    }
+
+    pub fn cpu_write(&mut self, a: u16, d: u8) {
+        if let Some(ram) = &mut self.flat_cpu_ram {
+            ram[a as usize] = d;
+        } else if a < 0x2000 {
+            self.cpu_ram[(a & 0x7ff) as usize] = d;
+        } else if a >= 0x8000 {
+            self.prg_ram[(a - 0x8000) as usize] = d;
+        }
+        // else external device (i.e. PPU) or the 2A03's own registers, such as sprite DMA
+    }
+
+    pub fn ppu_write(&mut self, mut a: u16, d: u8) {
+        a &= 0x3fff;
+        if a >= 0x3000 {
+            a -= 0x1000;
+        }
+
+        if a < 0x2000 {
+            self.chr_ram[a as usize] = d
+        } else {
+            self.nametable_ram[self.get_nametable(a) as usize][(a & 0x3ff) as usize] = d;
+        }
+    }
+
+    pub fn ppu_read(&self, mut a: u16) -> u8 {
+        a &= 0x3fff;
+        if a >= 0x3000 {
+            a -= 0x1000;
+        }
+
+        if a < 0x2000 {
+            self.chr_ram[a as usize]
+        } else {
+            self.nametable_ram[self.get_nametable(a) as usize][(a & 0x3ff) as usize]
+        }
+    }
+
+    pub fn get_nametable(&self, a: u16) -> u16 {
+        match self.mirroring_type {
+            MirroringType::Horizontal => {
+                if a & 0x800 > 0 {
+                    1
+                } else {
+                    0
+                }
+            }
+            MirroringType::Vertical => {
+                if a & 0x400 > 0 {
+                    1
+                } else {
+                    0
+                }
+            }
+            MirroringType::FourScreens => {
+                // TODO: Wouldn't this always equal 0?
+                // (a & 0xc00) >> 16
+                unimplemented!()
+            }
+            MirroringType::ScreenAOnly => 0,
+            MirroringType::ScreenBOnly => 1,
+        }
+    }
+}
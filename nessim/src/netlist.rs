@@ -0,0 +1,301 @@
+use crate::{
+    bit_set::BitSet,
+    channel_components::ChannelComponents,
+    components::{NodeDefinition, Transistor},
+    consts::{EMPTYNODE, IO_CE_LOW_HALF_STEPS, NODE_GND, NODE_PWR},
+};
+use std::{error::Error, fmt, path::Path};
+
+/// The chips in `data/` a netlist is built from.
+///
+/// Nodes keep their numbers whichever chips are loaded, so the node constants hold for any netlist
+/// containing the node's chip. The pins joining the chips are single nodes: `io_db0-7` are
+/// `cpu_db0-7`, `io_ab0-2` are `cpu_ab0-2`, `io_rw` is `cpu_rw` and `int` is `cpu_nmi`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chips {
+    /// The 2C02 wired to a 6502.
+    Both,
+    /// The 2C02 on its own, with nothing driving its CPU interface.
+    Ppu,
+    /// The 6502 on its own.
+    Cpu,
+}
+
+impl Chips {
+    pub fn has_ppu(self) -> bool {
+        self != Chips::Cpu
+    }
+
+    pub fn has_cpu(self) -> bool {
+        self != Chips::Ppu
+    }
+}
+
+/// The console region a netlist is for, which decides the chips it's built from and their timing.
+///
+/// The PAL and Dendy chips are loaded from their own directories under `data/`, with the same file
+/// names as the NTSC chips. They must number the nodes the simulation reads the same as the 2C02
+/// and 2A03 do, as the PAL chips' netlists, traced from the same design, do. Everything else about
+/// the region, such as how many lines a frame has and where vertical blank starts, comes from the
+/// netlist itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    /// A 2C02 and a 2A03, with the CPU dividing the master clock by 12 and the PPU by 4.
+    Ntsc,
+    /// A 2C07 and a 2A07, with the CPU dividing the master clock by 16 and the PPU by 5.
+    Pal,
+    /// A UA6538 PPU and a UA6527P CPU, with the CPU dividing the master clock by 15 and the PPU by
+    /// 5.
+    Dendy,
+}
+
+impl Region {
+    /// The directory holding the PPU's `segdefs.txt`, `transdefs.txt`, `palettenodes.txt` and
+    /// `spritenodes.txt`.
+    pub fn ppu_data_dir(self) -> &'static str {
+        match self {
+            Region::Ntsc => "data",
+            Region::Pal => "data/2c07",
+            Region::Dendy => "data/ua6538",
+        }
+    }
+
+    /// The directory holding the CPU's `cpusegdefs.txt` and `cputransdefs.txt`.
+    pub fn cpu_data_dir(self) -> &'static str {
+        match self {
+            Region::Ntsc => "data",
+            Region::Pal => "data/2a07",
+            Region::Dendy => "data/ua6527p",
+        }
+    }
+
+    pub fn master_clocks_per_cpu_cycle(self) -> u32 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    pub fn master_clocks_per_dot(self) -> u32 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    /// The number of distinct phases the CPU's clock divider can have relative to the PPU's.
+    ///
+    /// Both dividers start wherever they happen to power on, but shifting both by the same number
+    /// of master clocks changes nothing a program can observe, which leaves as many phases as the
+    /// greatest common divisor of the two dividers: four on NTSC, one on PAL and five on Dendy.
+    pub fn clock_alignments(self) -> u8 {
+        let (mut a, mut b) = (
+            self.master_clocks_per_cpu_cycle(),
+            self.master_clocks_per_dot(),
+        );
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a as u8
+    }
+
+    /// How long `io_ce` is held low for each access to a PPU register, which is the part of the
+    /// CPU cycle `M2` is high for.
+    pub fn io_ce_low_half_steps(self) -> u8 {
+        let half_steps = u32::from(IO_CE_LOW_HALF_STEPS) * self.master_clocks_per_cpu_cycle() / 12;
+        half_steps as u8
+    }
+
+    fn data_files(self, chips: Chips) -> Vec<String> {
+        let mut files = Vec::new();
+        if chips.has_ppu() {
+            files.push(format!("{}/segdefs.txt", self.ppu_data_dir()));
+            files.push(format!("{}/transdefs.txt", self.ppu_data_dir()));
+            files.push(format!("{}/palettenodes.txt", self.ppu_data_dir()));
+            files.push(format!("{}/spritenodes.txt", self.ppu_data_dir()));
+        }
+        if chips.has_cpu() {
+            files.push(format!("{}/cpusegdefs.txt", self.cpu_data_dir()));
+            files.push(format!("{}/cputransdefs.txt", self.cpu_data_dir()));
+        }
+        files
+    }
+}
+
+/// A netlist file a region needs that isn't under `data/`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingNetlistFile {
+    pub region: Region,
+    pub path: String,
+}
+
+impl fmt::Display for MissingNetlistFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the {:?} netlist needs {}", self.region, self.path)
+    }
+}
+
+impl Error for MissingNetlistFile {}
+
+/// The immutable topology of the processed netlist, flattened into contiguous arrays so the hot
+/// loops in the simulation don't chase a pointer per node.
+///
+/// Per-node lists are stored in compressed form: the entries for node `n` live at
+/// `offsets[n]..offsets[n + 1]` of the corresponding data array.
+///
+/// Alongside the topology it holds the rest of the data loaded from `data/` that every simulation
+/// of the chip needs but never modifies.
+#[derive(Clone)]
+pub struct Netlist {
+    pub node_areas: Vec<u64>,
+    pub gate_offsets: Vec<u32>,
+    /// Indices of the transistors gated by each node.
+    pub gates: Vec<u16>,
+    pub channel_offsets: Vec<u32>,
+    /// Indices of the transistors whose channel is connected to each node.
+    pub channel_transistors: Vec<u16>,
+    /// For each entry of `channel_transistors`, the node on the other side of the channel.
+    pub channel_neighbors: Vec<u16>,
+    pub transistors: Vec<Transistor>,
+    pub node_initial_pullup: BitSet,
+    pub transistors_initial_power_state: BitSet,
+    /// Every node except power and ground, used to settle the whole chip at power-on.
+    pub all_recalc_nodes: Vec<u16>,
+    pub sprite_nodes: Vec<Vec<(i32, i32)>>,
+    pub palette_nodes: Vec<Vec<(i32, i32)>>,
+    pub components: ChannelComponents,
+    pub chips: Chips,
+    pub region: Region,
+}
+
+impl Netlist {
+    pub fn load() -> Self {
+        Self::load_chips(Chips::Both)
+    }
+
+    pub fn load_chips(chips: Chips) -> Self {
+        Self::load_region(chips, Region::Ntsc).unwrap()
+    }
+
+    pub fn load_region(chips: Chips, region: Region) -> Result<Self, MissingNetlistFile> {
+        use crate::preprocessor::{
+            id_conversion_table, load_ppu_nodes, load_segment_definitions,
+            load_transistor_definitions, setup_nodes, setup_transistors,
+        };
+        if let Some(path) = region
+            .data_files(chips)
+            .into_iter()
+            .find(|path| !Path::new(path).exists())
+        {
+            return Err(MissingNetlistFile { region, path });
+        }
+
+        let conversion_table = id_conversion_table();
+        let seg_defs = load_segment_definitions(chips, region, &conversion_table);
+        let trans_defs = load_transistor_definitions(chips, region, &conversion_table);
+        let mut nodes = setup_nodes(&seg_defs);
+        let (palette_nodes, sprite_nodes) = load_ppu_nodes(region);
+        let mut transistors_initial_power_state = BitSet::new(trans_defs.len());
+        for (i, def) in trans_defs.iter().enumerate() {
+            transistors_initial_power_state.assign(i, def.gate == NODE_PWR);
+        }
+        let (transistors, node_counts, nodes_c1_c2, _) = setup_transistors(&mut nodes, trans_defs);
+
+        let mut netlist = Self::new(&nodes, transistors, &node_counts, &nodes_c1_c2);
+        netlist.transistors_initial_power_state = transistors_initial_power_state;
+        netlist.sprite_nodes = sprite_nodes;
+        netlist.palette_nodes = palette_nodes;
+        netlist.chips = chips;
+        netlist.region = region;
+        Ok(netlist)
+    }
+
+    pub fn new(
+        nodes: &[NodeDefinition],
+        transistors: Vec<Transistor>,
+        node_counts: &[u8],
+        nodes_c1_c2: &[Vec<u16>],
+    ) -> Self {
+        let mut gate_offsets = Vec::with_capacity(nodes.len() + 1);
+        let mut gates = Vec::new();
+        let mut channel_offsets = Vec::with_capacity(nodes.len() + 1);
+        let mut channel_transistors = Vec::new();
+        let mut channel_neighbors = Vec::new();
+
+        for (node_number, node) in nodes.iter().enumerate() {
+            gate_offsets.push(gates.len() as u32);
+            gates.extend_from_slice(&node.gates);
+
+            channel_offsets.push(channel_transistors.len() as u32);
+            let count = node_counts[node_number] as usize;
+            for transistor_index in &nodes_c1_c2[node_number][..count] {
+                let transistor = &transistors[*transistor_index as usize];
+                let neighbor = if transistor.c1 == node_number as u16 {
+                    transistor.c2
+                } else {
+                    transistor.c1
+                };
+                channel_transistors.push(*transistor_index);
+                channel_neighbors.push(neighbor);
+            }
+        }
+
+        gate_offsets.push(gates.len() as u32);
+        channel_offsets.push(channel_transistors.len() as u32);
+
+        let mut node_initial_pullup = BitSet::new(nodes.len());
+        for (i, node) in nodes.iter().enumerate() {
+            node_initial_pullup.assign(i, node.pullup);
+        }
+
+        let all_recalc_nodes = nodes
+            .iter()
+            .filter(|n| n.num != NODE_PWR && n.num != NODE_GND && n.num != EMPTYNODE)
+            .map(|n| n.num)
+            .collect();
+
+        let mut netlist = Netlist {
+            node_areas: nodes.iter().map(|node| node.area).collect(),
+            gate_offsets,
+            gates,
+            channel_offsets,
+            channel_transistors,
+            channel_neighbors,
+            transistors_initial_power_state: BitSet::new(transistors.len()),
+            transistors,
+            node_initial_pullup,
+            all_recalc_nodes,
+            sprite_nodes: Vec::new(),
+            palette_nodes: Vec::new(),
+            components: ChannelComponents::default(),
+            chips: Chips::Both,
+            region: Region::Ntsc,
+        };
+        netlist.components = ChannelComponents::new(&netlist);
+        netlist
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.node_areas.len()
+    }
+
+    pub fn transistor_count(&self) -> usize {
+        self.transistors.len()
+    }
+
+    #[inline]
+    pub fn gates(&self, node_number: usize) -> &[u16] {
+        let start = self.gate_offsets[node_number] as usize;
+        let end = self.gate_offsets[node_number + 1] as usize;
+        &self.gates[start..end]
+    }
+
+    #[inline]
+    pub fn channel_range(&self, node_number: usize) -> (u32, u32) {
+        (
+            self.channel_offsets[node_number],
+            self.channel_offsets[node_number + 1],
+        )
+    }
+}
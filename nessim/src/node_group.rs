@@ -0,0 +1,179 @@
+#[cfg(feature = "compiled")]
+use crate::consts::{NODE_GND, NODE_PWR};
+use crate::{
+    bit_set::BitSet,
+    channel_components::{LOCAL_GND, LOCAL_PWR},
+    consts::AREA_RESOLVED_NODES,
+    netlist::Netlist,
+};
+
+/// The set of nodes connected to a node through transistors that are on, along with the scratch
+/// space needed to find it.
+#[derive(Clone)]
+pub struct NodeGroup {
+    pub nodes: Vec<u16>,
+    pub has_ground: bool,
+    pub has_power: bool,
+    /// Pending (next, end) ranges into the component's channel lists while walking a group.
+    stack: Vec<(u32, u32)>,
+    /// A node belongs to the current group when the mark at its local index equals `generation`.
+    marks: Vec<u32>,
+    generation: u32,
+}
+
+impl NodeGroup {
+    pub fn new(netlist: &Netlist) -> Self {
+        NodeGroup {
+            nodes: Vec::new(),
+            has_ground: false,
+            has_power: false,
+            stack: Vec::new(),
+            marks: vec![0; netlist.components.largest_len()],
+            generation: 0,
+        }
+    }
+
+    /// Collect every node connected to `node_number` through transistors that are on.
+    ///
+    /// Only the node's channel-connected component is walked, using the component's local indices.
+    /// Nodes are visited in the same depth-first order as a recursive walk would visit them, since
+    /// `value` gives priority to whichever pulled node it encounters first.
+    pub fn collect(&mut self, netlist: &Netlist, transistor_on: &BitSet, node_number: u16) {
+        self.has_ground = false;
+        self.has_power = false;
+        self.nodes.clear();
+
+        self.generation = self.generation.wrapping_add(1);
+        if self.generation == 0 {
+            self.marks.iter_mut().for_each(|mark| *mark = 0);
+            self.generation = 1;
+        }
+        let generation = self.generation;
+
+        let components = &netlist.components;
+        let component = components.component_of(node_number);
+        let component_nodes = components.nodes(component);
+        let start = components.local_index(node_number);
+        self.marks[start] = generation;
+        self.nodes.push(node_number);
+        self.stack.clear();
+        self.stack.push(components.channel_range(component, start));
+
+        while let Some(range) = self.stack.last_mut() {
+            let (next, end) = *range;
+            if next == end {
+                self.stack.pop();
+                continue;
+            }
+            range.0 += 1;
+
+            let (transistor, neighbor) = components.channel(next);
+            if !transistor_on.get(transistor as usize) {
+                continue;
+            }
+
+            if neighbor == LOCAL_GND {
+                self.has_ground = true;
+            } else if neighbor == LOCAL_PWR {
+                self.has_power = true;
+            } else if self.marks[neighbor as usize] != generation {
+                let neighbor = neighbor as usize;
+                self.marks[neighbor] = generation;
+                self.nodes.push(component_nodes[neighbor]);
+                self.stack
+                    .push(components.channel_range(component, neighbor));
+            }
+        }
+    }
+
+    /// Collect the group of `node_number` from the channel tables the `compiled` feature
+    /// generates, in the same order as `collect`.
+    ///
+    /// `node_channels` holds the start and length of each node's run of `channels` and its index
+    /// within its component, and `channels` each channel's transistor and the node on the other
+    /// side. Visited nodes are
+    /// tracked by their index within the component, in a bit set of `WORDS` words.
+    #[cfg(feature = "compiled")]
+    pub fn collect_from_tables<const WORDS: usize>(
+        &mut self,
+        transistor_on: &BitSet,
+        node_number: u16,
+        node_channels: &[(u32, u16, u16)],
+        channels: &[(u16, u16)],
+    ) {
+        self.has_ground = false;
+        self.has_power = false;
+        self.nodes.clear();
+
+        let mut visited = [0_u64; WORDS];
+        let (start, len, local_index) = node_channels[node_number as usize];
+        visited[local_index as usize / 64] |= 1 << (local_index % 64);
+        self.nodes.push(node_number);
+        self.stack.clear();
+        self.stack.push((start, start + u32::from(len)));
+
+        while let Some(range) = self.stack.last_mut() {
+            let (next, end) = *range;
+            if next == end {
+                self.stack.pop();
+                continue;
+            }
+            range.0 += 1;
+
+            let (transistor, neighbor) = channels[next as usize];
+            if !transistor_on.get(transistor as usize) {
+                continue;
+            }
+
+            if neighbor == NODE_GND {
+                self.has_ground = true;
+            } else if neighbor == NODE_PWR {
+                self.has_power = true;
+            } else {
+                let (start, len, local_index) = node_channels[neighbor as usize];
+                let (word, bit) = (local_index as usize / 64, 1 << (local_index % 64));
+                if visited[word] & bit == 0 {
+                    visited[word] |= bit;
+                    self.nodes.push(neighbor);
+                    self.stack.push((start, start + u32::from(len)));
+                }
+            }
+        }
+    }
+
+    /// The value the group settles to.
+    pub fn value(
+        &self,
+        netlist: &Netlist,
+        node_state: &BitSet,
+        node_pullup: &BitSet,
+        node_pulldown: &BitSet,
+    ) -> bool {
+        let area_resolved = self.has_ground
+            && self.has_power
+            && self.nodes.iter().any(|n| AREA_RESOLVED_NODES.contains(n));
+
+        if self.has_ground && !area_resolved {
+            false
+        } else if self.has_power && !area_resolved {
+            true
+        } else {
+            let mut hi_area = 0_u64;
+            let mut lo_area = 0_u64;
+            for node_number in &self.nodes {
+                let node_number = *node_number as usize;
+                if node_pullup.get(node_number) {
+                    return true;
+                } else if node_pulldown.get(node_number) {
+                    return false;
+                } else if node_state.get(node_number) {
+                    hi_area += netlist.node_areas[node_number]
+                } else {
+                    lo_area += netlist.node_areas[node_number]
+                }
+            }
+
+            hi_area > lo_area
+        }
+    }
+}
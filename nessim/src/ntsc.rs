@@ -0,0 +1,184 @@
+use crate::{frame_counter::VISIBLE_SCANLINES, palette::NtscPaletteOptions};
+use std::f64::consts::PI;
+
+/// The composite samples the PPU outputs for each dot, one per edge of the master clock.
+pub const SAMPLES_PER_DOT: usize = 8;
+
+/// The number of master clock edges in a cycle of the colour subcarrier.
+pub const SUBCARRIER_PHASES: u8 = 12;
+
+const DOTS_PER_LINE: usize = 256;
+const SAMPLES_PER_LINE: usize = DOTS_PER_LINE * SAMPLES_PER_DOT;
+
+/// Signal levels in volts for each of the four luminances, low then high.
+const LOW: [f64; 4] = [0.350, 0.518, 0.962, 1.550];
+const HIGH: [f64; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK: f64 = 0.518;
+const WHITE: f64 = 1.962;
+const EMPHASIS_ATTENUATION: f64 = 0.746;
+
+/// Whether a colour's square wave is high at a phase of the colour subcarrier.
+fn is_in_phase(phase: u8, colour: u16) -> bool {
+    (colour + u16::from(phase) + 8) % 12 < 6
+}
+
+/// The level of the 2C02's composite signal for a 9-bit pixel at a phase of the colour subcarrier,
+/// scaled so black is 0 and white is 1.
+///
+/// The PPU switches between a low and a high level for the pixel's luminance on a square wave whose
+/// phase is the pixel's hue. Greys and blacks stay at one level, and each emphasis bit attenuates
+/// the signal for the half of the subcarrier cycle centred on its colour.
+pub(crate) fn signal_level(pixel: u16, phase: u8) -> f32 {
+    let colour = pixel & 0x0f;
+    let luminance = if colour < 0x0e {
+        (pixel as usize >> 4) & 0x03
+    } else {
+        1
+    };
+    let low = if colour == 0x00 { HIGH } else { LOW }[luminance];
+    let high = if colour < 0x0d { HIGH } else { LOW }[luminance];
+
+    let mut level = if is_in_phase(phase, colour) {
+        high
+    } else {
+        low
+    };
+    if (pixel & 0x40 != 0 && is_in_phase(phase, 12))
+        || (pixel & 0x80 != 0 && is_in_phase(phase, 4))
+        || (pixel & 0x100 != 0 && is_in_phase(phase, 8))
+    {
+        level *= EMPHASIS_ATTENUATION;
+    }
+    ((level - BLACK) / (WHITE - BLACK)) as f32
+}
+
+/// Decode a colour subcarrier cycle's worth of samples, each with its phase, as an ideal NTSC
+/// television would.
+pub(crate) fn decode<I: IntoIterator<Item = (f32, u8)>>(
+    samples: I,
+    options: &NtscPaletteOptions,
+) -> u32 {
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+    for (level, phase) in samples {
+        let v = f64::from(level) / f64::from(SUBCARRIER_PHASES);
+        let angle = PI * f64::from(phase) / 6.0 + options.hue.to_radians();
+        y += v;
+        i += v * angle.cos();
+        q += v * angle.sin();
+    }
+
+    let gamma_correct = |c: f64| {
+        let c = if c <= 0.0 {
+            0.0
+        } else {
+            c.powf(2.2 / options.gamma)
+        };
+        (255.95 * c).min(255.0) as u32
+    };
+    let y = y * options.contrast + options.brightness;
+    let i = i * options.contrast * options.saturation;
+    let q = q * options.contrast * options.saturation;
+    let r = gamma_correct(y + 0.946_882 * i + 0.623_557 * q);
+    let g = gamma_correct(y - 0.274_788 * i - 0.635_691 * q);
+    let b = gamma_correct(y - 1.108_545 * i + 1.709_007 * q);
+    0xff00_0000 | r << 16 | g << 8 | b
+}
+
+/// The composite signal of the visible part of a frame, as the PPU output it.
+///
+/// Each dot's samples start at the phase of the colour subcarrier the master clock was at, so
+/// the picture shifts against the subcarrier from line to line and frame to frame as it does on
+/// the console.
+#[derive(Clone)]
+pub struct CompositeFrame {
+    samples: Box<[f32]>,
+    phases: Box<[u8]>,
+}
+
+impl CompositeFrame {
+    pub(crate) fn new() -> Self {
+        let dots = DOTS_PER_LINE * VISIBLE_SCANLINES as usize;
+        CompositeFrame {
+            samples: vec![0.0; dots * SAMPLES_PER_DOT].into_boxed_slice(),
+            phases: vec![0; dots].into_boxed_slice(),
+        }
+    }
+
+    /// `SAMPLES_PER_DOT` samples for each dot, in rows of 256 dots from the top, as returned by
+    /// `signal_level`.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// The subcarrier phase of each dot's first sample. The rest follow on from it.
+    pub fn phases(&self) -> &[u8] {
+        &self.phases
+    }
+
+    pub(crate) fn write_dot(&mut self, dot: usize, pixel: u16, phase: u8) {
+        self.phases[dot] = phase;
+        let samples = &mut self.samples[dot * SAMPLES_PER_DOT..(dot + 1) * SAMPLES_PER_DOT];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = signal_level(pixel, (phase + i as u8) % SUBCARRIER_PHASES);
+        }
+    }
+
+    /// Decode the signal into a 256x240 ARGB picture, one subcarrier cycle around the middle of
+    /// each dot at a time. Sharp changes of colour bleed into their neighbours and fringe with
+    /// colours that aren't in the palette, as on a television.
+    ///
+    /// Only the visible dots are recorded, so past either end of a line the cycle is taken to be
+    /// at the black level, which dims and tints the dots at the edges of the picture.
+    pub fn decode(&self, options: &NtscPaletteOptions) -> Vec<u32> {
+        let mut argb = Vec::with_capacity(self.phases.len());
+        for line in 0..VISIBLE_SCANLINES as usize {
+            let line_samples =
+                &self.samples[line * SAMPLES_PER_LINE..(line + 1) * SAMPLES_PER_LINE];
+            let line_phases = &self.phases[line * DOTS_PER_LINE..(line + 1) * DOTS_PER_LINE];
+            for dot in 0..DOTS_PER_LINE {
+                let middle = dot * SAMPLES_PER_DOT + SAMPLES_PER_DOT / 2;
+                let window = (middle..middle + 12).map(|sample| match sample.checked_sub(6) {
+                    Some(sample) if sample < SAMPLES_PER_LINE => {
+                        let first_phase = line_phases[sample / SAMPLES_PER_DOT];
+                        let offset = (sample % SAMPLES_PER_DOT) as u8;
+                        (
+                            line_samples[sample],
+                            (first_phase + offset) % SUBCARRIER_PHASES,
+                        )
+                    }
+                    _ => (0.0, 0),
+                });
+                argb.push(decode(window, options));
+            }
+        }
+        argb
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoding_a_flat_colour_matches_the_generated_palette() {
+        let options = NtscPaletteOptions::default();
+        let palette = crate::palette::Palette::generate_ntsc(&options);
+        let mut frame = CompositeFrame::new();
+        for dot in 0..DOTS_PER_LINE {
+            let phase = (dot * SAMPLES_PER_DOT % 12) as u8;
+            frame.write_dot(dot, 0x16, phase);
+        }
+
+        let argb = frame.decode(&options);
+        let channels = |argb: u32| [(argb >> 16) as u8, (argb >> 8) as u8, argb as u8];
+        for (expected, actual) in channels(palette.argb(0x16))
+            .iter()
+            .zip(&channels(argb[100]))
+        {
+            assert!(expected.abs_diff(*actual) <= 1);
+        }
+        // Part of the first dot's cycle is off the picture, at the black level.
+        let brightness = |argb: u32| channels(argb).iter().map(|c| u32::from(*c)).sum::<u32>();
+        assert!(brightness(argb[0]) < brightness(palette.argb(0x16)));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::{
+    consts::PALETTE_ARGB,
+    netlist::Region,
+    ntsc::{self, signal_level, SUBCARRIER_PHASES},
+};
+use std::{
+    error::Error,
+    fmt,
+    io::{self, Read},
+};
+
+/// The number of distinct pixels the PPU outputs: a 6-bit palette index and 3 emphasis bits.
+pub const PALETTE_ENTRIES: usize = 512;
+
+/// How much emphasising a colour dims the other two, in thousandths.
+const EMPHASIS_ATTENUATION: u32 = 816;
+
+/// What the framebuffers are filled with as the PPU outputs pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    /// ARGB colours from the palette.
+    Argb,
+    /// The 9-bit pixels themselves, so frames can be compared or coloured independently of any
+    /// palette.
+    RawPixels,
+    Both,
+}
+
+impl OutputMode {
+    pub fn has_argb(self) -> bool {
+        self != OutputMode::RawPixels
+    }
+
+    pub fn has_raw_pixels(self) -> bool {
+        self != OutputMode::Argb
+    }
+}
+
+/// The ARGB colour of every 9-bit pixel, with the palette index in the low six bits and the
+/// emphasis bits from PPUMASK above them.
+///
+/// Greyscale needs no entries of its own, since the PPU applies it to the palette index before it
+/// leaves the palette RAM.
+#[derive(Clone)]
+pub struct Palette {
+    argb: Box<[u32; PALETTE_ENTRIES]>,
+}
+
+impl Palette {
+    /// The palette a console of `region` is shown with until another is set.
+    ///
+    /// The 2C07 and the UA6538 swap the meaning of PPUMASK's red and green emphasis bits, so their
+    /// palette is the NTSC one with the entries for those two bits swapped.
+    pub fn for_region(region: Region) -> Self {
+        let ntsc = Palette::default();
+        match region {
+            Region::Ntsc => ntsc,
+            Region::Pal | Region::Dendy => {
+                let mut argb = Box::new([0; PALETTE_ENTRIES]);
+                for (pixel, entry) in argb.iter_mut().enumerate() {
+                    let swapped = pixel & !0xc0 | (pixel & 0x40) << 1 | (pixel & 0x80) >> 1;
+                    *entry = ntsc.argb[swapped];
+                }
+                Palette { argb }
+            }
+        }
+    }
+
+    /// Extend 64 colours to every emphasis combination by dimming the colour components that
+    /// aren't emphasised, as the 2C02 does. Emphasising all three dims everything.
+    pub fn with_emphasis(base: &[u32; 64]) -> Self {
+        let mut argb = Box::new([0; PALETTE_ENTRIES]);
+        for (pixel, entry) in argb.iter_mut().enumerate() {
+            let colour = base[pixel & 0x3f];
+            let emphasis = pixel >> 6;
+            if emphasis == 0 || pixel & 0x0e == 0x0e {
+                // Blacks in columns $E and $F are below what emphasis dims.
+                *entry = colour;
+                continue;
+            }
+
+            *entry = colour & 0xff00_0000;
+            // Red is the top byte of the three, blue the bottom.
+            for (channel, shift) in [16, 8, 0].iter().enumerate() {
+                let mut component = (colour >> shift) & 0xff;
+                if emphasis == 0x07 || emphasis & (1 << channel) == 0 {
+                    component = component * EMPHASIS_ATTENUATION / 1000;
+                }
+                *entry |= component << shift;
+            }
+        }
+        Palette { argb }
+    }
+
+    pub fn from_argb(argb: &[u32; PALETTE_ENTRIES]) -> Self {
+        Palette {
+            argb: Box::new(*argb),
+        }
+    }
+
+    /// Load a `.pal` file: RGB triples for either the 64 palette indices, which are extended with
+    /// `with_emphasis`, or all 512 pixels.
+    pub fn load_pal<R: Read>(input: &mut R) -> Result<Self, LoadPaletteError> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        if bytes.len() != 192 && bytes.len() != 1536 {
+            return Err(LoadPaletteError::Size(bytes.len()));
+        }
+
+        let argb = bytes
+            .chunks(3)
+            .map(|rgb| {
+                0xff00_0000 | u32::from(rgb[0]) << 16 | u32::from(rgb[1]) << 8 | u32::from(rgb[2])
+            })
+            .collect::<Vec<u32>>();
+
+        if argb.len() == 64 {
+            let mut base = [0; 64];
+            base.copy_from_slice(&argb);
+            Ok(Palette::with_emphasis(&base))
+        } else {
+            let mut all = [0; PALETTE_ENTRIES];
+            all.copy_from_slice(&argb);
+            Ok(Palette::from_argb(&all))
+        }
+    }
+
+    /// Generate a palette by modelling the 2C02's composite video signal for each pixel and decoding
+    /// it as an ideal NTSC television would, emphasis included.
+    pub fn generate_ntsc(options: &NtscPaletteOptions) -> Self {
+        let mut argb = [0; PALETTE_ENTRIES];
+        for (pixel, entry) in argb.iter_mut().enumerate() {
+            let cycle =
+                (0..SUBCARRIER_PHASES).map(|phase| (signal_level(pixel as u16, phase), phase));
+            *entry = ntsc::decode(cycle, options);
+        }
+        Palette::from_argb(&argb)
+    }
+
+    pub fn argb(&self, pixel: u16) -> u32 {
+        self.argb[pixel as usize & (PALETTE_ENTRIES - 1)]
+    }
+}
+
+/// Adjustments to the picture made by `Palette::generate_ntsc` and `CompositeFrame::decode`, like a television's controls.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NtscPaletteOptions {
+    /// Degrees to rotate every colour's hue by.
+    pub hue: f64,
+    /// How strongly colours are shown, from 0 for greyscale.
+    pub saturation: f64,
+    pub contrast: f64,
+    /// Added to every colour's luminance, where 1 is the difference between black and white.
+    pub brightness: f64,
+    /// The gamma of the television being imitated, whose output is corrected for a 2.2 display.
+    pub gamma: f64,
+}
+
+impl Default for NtscPaletteOptions {
+    fn default() -> Self {
+        NtscPaletteOptions {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            gamma: 1.8,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadPaletteError {
+    Io(io::Error),
+    /// The file is this many bytes long, rather than 192 or 1536.
+    Size(usize),
+}
+
+impl fmt::Display for LoadPaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadPaletteError::Io(error) => write!(f, "couldn't read the palette: {}", error),
+            LoadPaletteError::Size(len) => write!(
+                f,
+                "a palette is 192 or 1536 bytes long, but this one is {}",
+                len
+            ),
+        }
+    }
+}
+
+impl Error for LoadPaletteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadPaletteError::Io(error) => Some(error),
+            LoadPaletteError::Size(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadPaletteError {
+    fn from(error: io::Error) -> Self {
+        LoadPaletteError::Io(error)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::with_emphasis(&PALETTE_ARGB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pal_palettes_swap_red_and_green_emphasis() {
+        let ntsc = Palette::for_region(Region::Ntsc);
+        let pal = Palette::for_region(Region::Pal);
+        assert_eq!(ntsc.argb(0x016), pal.argb(0x016));
+        assert_eq!(ntsc.argb(0x056), pal.argb(0x096));
+        assert_eq!(ntsc.argb(0x096), pal.argb(0x056));
+        assert_eq!(ntsc.argb(0x116), pal.argb(0x116));
+        assert_eq!(ntsc.argb(0x1d6), pal.argb(0x1d6));
+    }
+
+    #[test]
+    fn emphasis_dims_the_other_components() {
+        let palette = Palette::with_emphasis(&[0xff80_8080; 64]);
+        assert_eq!(0xff80_8080, palette.argb(0x00));
+        // Red only.
+        assert_eq!(0xff80_6868, palette.argb(0x40));
+        // Green and blue.
+        assert_eq!(0xff68_8080, palette.argb(0x180));
+        assert_eq!(0xff68_6868, palette.argb(0x1c0));
+        assert_eq!(0xff80_8080, palette.argb(0x1cf));
+    }
+
+    #[test]
+    fn pal_files_hold_64_or_512_colours() {
+        let mut pal = [0; 192];
+        pal[3..6].copy_from_slice(&[0x12, 0x34, 0x56]);
+        let palette = Palette::load_pal(&mut &pal[..]).unwrap();
+        assert_eq!(0xff12_3456, palette.argb(0x01));
+        assert_eq!(0xff0e_3446, palette.argb(0x81));
+
+        let mut pal = [0; 1536];
+        pal[1533..].copy_from_slice(&[0xab, 0xcd, 0xef]);
+        let palette = Palette::load_pal(&mut &pal[..]).unwrap();
+        assert_eq!(0xffab_cdef, palette.argb(0x1ff));
+
+        match Palette::load_pal(&mut &[0_u8; 100][..]) {
+            Err(LoadPaletteError::Size(100)) => {}
+            _ => panic!("expected a size error"),
+        }
+    }
+
+    #[test]
+    fn generated_palette_has_greys_and_blacks() {
+        let palette = Palette::generate_ntsc(&NtscPaletteOptions::default());
+        let channels = |pixel| {
+            let argb = palette.argb(pixel);
+            ((argb >> 16) & 0xff, (argb >> 8) & 0xff, argb & 0xff)
+        };
+
+        for grey in &[0x00, 0x10, 0x20, 0x30] {
+            let (r, g, b) = channels(*grey);
+            assert!(r.max(g).max(b) - r.min(g).min(b) <= 1);
+        }
+        assert_eq!((0, 0, 0), channels(0x0f));
+        assert!(channels(0x30).0 > channels(0x00).0);
+
+        let (r, g, b) = channels(0x16);
+        assert!(r > g && r > b);
+
+        let greyscale = Palette::generate_ntsc(&NtscPaletteOptions {
+            saturation: 0.0,
+            ..NtscPaletteOptions::default()
+        });
+        let argb = greyscale.argb(0x16);
+        assert_eq!((argb >> 16) & 0xff, argb & 0xff);
+    }
+}
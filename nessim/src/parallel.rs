@@ -0,0 +1,152 @@
+use crate::{
+    bit_set::BitSet,
+    consts::{NODE_GND, NODE_PWR},
+    netlist::Netlist,
+    node_group::NodeGroup,
+    SimulationState,
+};
+use rayon::prelude::*;
+
+/// Recalc lists shorter than this are evaluated serially, since handing them to the thread pool
+/// costs more than it saves.
+const DEFAULT_MIN_LIST_LEN: usize = 512;
+
+/// Scratch space for evaluating one slice of a recalc list on a worker thread.
+#[derive(Clone)]
+struct Worker {
+    group: NodeGroup,
+    /// The groups found for every node in the slice, stored back to back.
+    nodes: Vec<u16>,
+    /// For each node in the slice, the (start, end) range of its group within `nodes` and the value
+    /// the group settles to.
+    results: Vec<(u32, u32, bool)>,
+}
+
+/// Evaluates the groups in a recalc list on the rayon thread pool.
+///
+/// Every group in the list is first resolved in parallel against the node and transistor states at
+/// the start of the iteration. The results are then applied serially in list order, the same order
+/// the serial engine uses. A group and its value can only differ from the speculative result if one
+/// of its nodes changed state, or if a transistor touching one of its nodes switched, earlier in the
+/// same iteration. Such nodes are marked stale while applying, and any group containing a stale node
+/// is resolved again before it is applied. This keeps the outcome bit-identical to the serial
+/// engine.
+#[derive(Clone)]
+pub struct ParallelRecalc {
+    workers: Vec<Worker>,
+    stale_nodes: BitSet,
+    pub min_list_len: usize,
+}
+
+impl ParallelRecalc {
+    fn new(netlist: &Netlist) -> Self {
+        let worker_count = rayon::current_num_threads().max(1);
+        ParallelRecalc {
+            workers: (0..worker_count)
+                .map(|_| Worker {
+                    group: NodeGroup::new(netlist),
+                    nodes: Vec::new(),
+                    results: Vec::new(),
+                })
+                .collect(),
+            stale_nodes: BitSet::new(netlist.node_count()),
+            min_list_len: DEFAULT_MIN_LIST_LEN,
+        }
+    }
+}
+
+impl SimulationState {
+    /// Enable or disable evaluating large recalc lists on the rayon thread pool.
+    pub fn set_parallel_recalc(&mut self, enabled: bool) {
+        self.parallel_recalc = if enabled {
+            Some(ParallelRecalc::new(&self.netlist))
+        } else {
+            None
+        };
+    }
+
+    pub(crate) fn should_recalc_in_parallel(&self) -> bool {
+        match &self.parallel_recalc {
+            Some(parallel_recalc) => {
+                self.recalc_swap_list.cur_len() >= parallel_recalc.min_list_len
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn recalc_cur_list_in_parallel(&mut self) {
+        let mut parallel_recalc = self
+            .parallel_recalc
+            .take()
+            .expect("parallel recalc is enabled");
+
+        let list = self.recalc_swap_list.cur_list();
+        let chunk_len = list.len().div_ceil(parallel_recalc.workers.len()).max(1);
+        let netlist = &self.netlist;
+        let node_state = &self.node_state;
+        let node_pullup = &self.node_pullup;
+        let node_pulldown = &self.node_pulldown;
+        let transistor_on = &self.transistor_on;
+
+        parallel_recalc
+            .workers
+            .par_iter_mut()
+            .zip(list.par_chunks(chunk_len))
+            .for_each(|(worker, chunk)| {
+                worker.nodes.clear();
+                worker.results.clear();
+                for node_number in chunk {
+                    let node_number = *node_number;
+                    let start = worker.nodes.len() as u32;
+                    if node_number == NODE_GND || node_number == NODE_PWR {
+                        worker.results.push((start, start, false));
+                        continue;
+                    }
+
+                    worker.group.collect(netlist, transistor_on, node_number);
+                    let value = worker
+                        .group
+                        .value(netlist, node_state, node_pullup, node_pulldown);
+                    worker.nodes.extend_from_slice(&worker.group.nodes);
+                    worker
+                        .results
+                        .push((start, worker.nodes.len() as u32, value));
+                }
+            });
+
+        parallel_recalc.stale_nodes.clear_all();
+        for i in 0..self.recalc_swap_list.cur_len() {
+            let node_number = self.recalc_swap_list.cur_node(i);
+            if node_number == NODE_GND || node_number == NODE_PWR {
+                continue;
+            }
+
+            let worker = &parallel_recalc.workers[i / chunk_len];
+            let (start, end, value) = worker.results[i % chunk_len];
+            let nodes = &worker.nodes[start as usize..end as usize];
+            let stale_nodes = &parallel_recalc.stale_nodes;
+            let new_state = if nodes.iter().any(|n| stale_nodes.get(*n as usize)) {
+                self.resolve_group(node_number)
+            } else {
+                self.group.nodes.clear();
+                self.group.nodes.extend_from_slice(nodes);
+                value
+            };
+
+            for node_number in &self.group.nodes {
+                let node_number = *node_number as usize;
+                if self.node_state.get(node_number) != new_state {
+                    parallel_recalc.stale_nodes.set(node_number);
+                    for i in self.netlist.gates(node_number) {
+                        let transistor = &self.netlist.transistors[*i as usize];
+                        parallel_recalc.stale_nodes.set(transistor.c1 as usize);
+                        parallel_recalc.stale_nodes.set(transistor.c2 as usize);
+                    }
+                }
+            }
+            self.set_group_state(new_state);
+        }
+
+        self.parallel_recalc = Some(parallel_recalc);
+    }
+}
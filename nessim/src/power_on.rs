@@ -0,0 +1,137 @@
+/// Which of the region's `clock_alignments` CPU/PPU clock phases the console powers on in.
+///
+/// Each clock divider starts wherever it happens to power on, so a CPU cycle can begin on any
+/// master clock of a PPU dot that the two dividers' phases allow: on NTSC, where the CPU divides
+/// the master clock by 12 and the PPU by 4, that's any of four. Alignment 0 is the phase the
+/// netlist settles into on its own, and each alignment after it delays the PPU by another master
+/// clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClockAlignment {
+    /// Always power on in this alignment, which must be less than the region's
+    /// `clock_alignments`.
+    Fixed(u8),
+    /// Power on in an alignment picked from this seed. The same seed always picks the same one.
+    Random(u64),
+}
+
+impl ClockAlignment {
+    /// The alignment to power on in, out of `alignments`.
+    pub(crate) fn resolve(self, alignments: u8) -> u8 {
+        match self {
+            ClockAlignment::Fixed(alignment) => {
+                assert!(
+                    alignment < alignments,
+                    "there are only {} clock alignments",
+                    alignments
+                );
+                alignment
+            }
+            ClockAlignment::Random(seed) => {
+                (Rng::new(seed).next_u64() % u64::from(alignments)) as u8
+            }
+        }
+    }
+}
+
+/// What a RAM holds at power-on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RamFill {
+    Zeros,
+    /// Every byte is `$FF`.
+    Ones,
+    /// Four bytes of `$00` then four of `$FF`, repeated, as many consoles' CPU RAM powers on with.
+    Alternating,
+    /// Bytes from a generator seeded with this.
+    Random(u64),
+}
+
+impl RamFill {
+    pub(crate) fn fill<'a, I: IntoIterator<Item = &'a mut u8>>(self, ram: I) {
+        match self {
+            RamFill::Zeros => ram.into_iter().for_each(|b| *b = 0),
+            RamFill::Ones => ram.into_iter().for_each(|b| *b = 0xff),
+            RamFill::Alternating => ram
+                .into_iter()
+                .enumerate()
+                .for_each(|(i, b)| *b = if i & 0x04 == 0 { 0 } else { 0xff }),
+            RamFill::Random(seed) => {
+                let mut rng = Rng::new(seed);
+                ram.into_iter().for_each(|b| *b = rng.next_u64() as u8);
+            }
+        }
+    }
+}
+
+/// The state the console is in when it's powered on, by `load_rom` or `power_cycle`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerOnOptions {
+    pub clock_alignment: ClockAlignment,
+    pub cpu_ram: RamFill,
+    pub nametable_ram: RamFill,
+    /// When set, every node nothing drives at power-on starts in a state picked from this seed
+    /// instead of low. That covers the palette and OAM cells and the latches inside both chips,
+    /// which the first recalculation of the whole chip then settles from those states.
+    pub random_undriven_nodes: Option<u64>,
+}
+
+impl Default for PowerOnOptions {
+    fn default() -> Self {
+        PowerOnOptions {
+            clock_alignment: ClockAlignment::Fixed(0),
+            cpu_ram: RamFill::Zeros,
+            nametable_ram: RamFill::Zeros,
+            random_undriven_nodes: None,
+        }
+    }
+}
+
+/// A small seeded generator (SplitMix64) so randomised power-on states can be reproduced.
+#[derive(Clone)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netlist::Region;
+
+    #[test]
+    fn alternating_fill_repeats_every_eight_bytes() {
+        let mut ram = [0x55; 16];
+        RamFill::Alternating.fill(ram.iter_mut());
+        assert_eq!(
+            [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff],
+            ram
+        );
+    }
+
+    #[test]
+    fn random_alignments_are_reproducible_and_cover_every_phase() {
+        let count = Region::Ntsc.clock_alignments();
+        let alignments = (0..64)
+            .map(|seed| ClockAlignment::Random(seed).resolve(count))
+            .collect::<Vec<u8>>();
+        let again = (0..64)
+            .map(|seed| ClockAlignment::Random(seed).resolve(count))
+            .collect::<Vec<u8>>();
+        assert_eq!(alignments, again);
+        for alignment in 0..count {
+            assert!(alignments.contains(&alignment));
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use crate::{
+    consts::{CPU_AB_NODES, IO_CE_LOW_HALF_STEPS, NODE_CPU_RW, NODE_IO_CE},
+    event_log::PpuEventLog,
+    netlist::{Chips, Netlist},
+    palette::OutputMode,
+    SimulationState,
+};
+use std::{
+    error::Error,
+    fmt,
+    io::{Read, Seek},
+    sync::Arc,
+};
+
+/// An access to one of the PPU's registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterAccess {
+    Read { addr: u16, record: bool },
+    Write { addr: u16, val: u8 },
+}
+
+/// One line of a `RegisterScript`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScriptCommand {
+    /// The (scanline, dot) to wait for, or `None` to follow the previous command immediately.
+    pub position: Option<(u16, u16)>,
+    pub access: RegisterAccess,
+}
+
+/// A sequence of register accesses, parsed from lines such as:
+///
+/// ```text
+/// # Comments and blank lines are ignored.
+/// at dot 0 of scanline 0 write $2001=$1E
+/// at dot 1 of scanline 241 read $2002 and record
+/// read $2002
+/// ```
+///
+/// Commands run in order. One with a position starts on the half-step at which the PPU's
+/// horizontal and vertical counters move to it, so waiting for a position already passed waits for
+/// the next frame. One without starts as soon as the previous command is done. Reads are only
+/// logged when followed by `and record`. Numbers are decimal, or hexadecimal with a `$` or `0x`
+/// prefix.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RegisterScript {
+    pub commands: Vec<ScriptCommand>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseScriptError {
+    /// The line the error was found on, starting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseScriptError {}
+
+impl RegisterScript {
+    pub fn parse(script: &str) -> Result<Self, ParseScriptError> {
+        let mut commands = Vec::new();
+        for (i, line) in script.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let line = line.replace('=', " = ");
+            let tokens = line.split_whitespace().collect::<Vec<&str>>();
+            if tokens.is_empty() {
+                continue;
+            }
+
+            let command = parse_command(&tokens).map_err(|message| ParseScriptError {
+                line: i + 1,
+                message,
+            })?;
+            commands.push(command);
+        }
+        Ok(RegisterScript { commands })
+    }
+}
+
+fn parse_command(tokens: &[&str]) -> Result<ScriptCommand, String> {
+    let mut tokens = tokens.iter().copied();
+    let mut token = tokens.next();
+    let mut position = None;
+    if token == Some("at") {
+        expect(&mut tokens, "dot")?;
+        let dot = parse_number(tokens.next(), 340)?;
+        expect(&mut tokens, "of")?;
+        expect(&mut tokens, "scanline")?;
+        let scanline = parse_number(tokens.next(), 261)?;
+        position = Some((scanline as u16, dot as u16));
+        token = tokens.next();
+    }
+
+    let access = match token {
+        Some("read") => {
+            let addr = parse_register(tokens.next())?;
+            let record = match tokens.next() {
+                None => false,
+                Some("and") => {
+                    expect(&mut tokens, "record")?;
+                    true
+                }
+                Some(token) => return Err(format!("expected `and record`, found `{}`", token)),
+            };
+            RegisterAccess::Read { addr, record }
+        }
+        Some("write") => {
+            let addr = parse_register(tokens.next())?;
+            expect(&mut tokens, "=")?;
+            let val = parse_number(tokens.next(), 0xff)? as u8;
+            RegisterAccess::Write { addr, val }
+        }
+        Some(token) => return Err(format!("expected `read` or `write`, found `{}`", token)),
+        None => return Err(String::from("expected `read` or `write`")),
+    };
+
+    match tokens.next() {
+        None => Ok(ScriptCommand { position, access }),
+        Some(token) => Err(format!("unexpected `{}`", token)),
+    }
+}
+
+fn expect<'a, I: Iterator<Item = &'a str>>(tokens: &mut I, expected: &str) -> Result<(), String> {
+    match tokens.next() {
+        Some(token) if token == expected => Ok(()),
+        Some(token) => Err(format!("expected `{}`, found `{}`", expected, token)),
+        None => Err(format!("expected `{}`", expected)),
+    }
+}
+
+fn parse_number(token: Option<&str>, max: u32) -> Result<u32, String> {
+    let token = token.ok_or_else(|| String::from("expected a number"))?;
+    let parsed = if let Some(hex) = token.strip_prefix('$') {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(hex) = token.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else {
+        token.parse::<u32>()
+    };
+
+    match parsed {
+        Ok(number) if number <= max => Ok(number),
+        Ok(_) => Err(format!("`{}` is larger than {}", token, max)),
+        Err(_) => Err(format!("`{}` isn't a number", token)),
+    }
+}
+
+fn parse_register(token: Option<&str>) -> Result<u16, String> {
+    let addr = parse_number(token, 0x3fff)?;
+    if addr < 0x2000 {
+        return Err(format!("${:04X} isn't a PPU register", addr));
+    }
+    Ok(addr as u16)
+}
+
+/// A read made by a script with `and record`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordedRead {
+    /// The position the read started at.
+    pub scanline: u16,
+    pub dot: u16,
+    pub addr: u16,
+    pub val: u8,
+}
+
+/// The length of a CPU cycle, of which the PPU is selected for the first `IO_CE_LOW_HALF_STEPS`.
+const CPU_CYCLE_HALF_STEPS: u8 = 24;
+
+#[derive(Clone, Copy)]
+enum Access {
+    Idle,
+    /// `io_ce` is low.
+    Selected,
+    /// `io_ce` is back high. The data bus and `io_rw` are released on the next half-step.
+    Releasing,
+    /// Waiting for the CPU cycle the access takes to end.
+    Released,
+}
+
+/// A 2C02 simulated without the 6502, its register interface driven by a `RegisterScript`.
+///
+/// Each access drives `io_ab0-2` and `io_rw`, and `io_db0-7` for writes, then holds `io_ce` low
+/// for as long as the 74139 does on the console. Reads are sampled from `io_db0-7` on the last
+/// half-step before `io_ce` goes high. Like a CPU access, each one takes a CPU cycle, so the next
+/// command starts no sooner than 24 half-steps later.
+///
+/// The PPU ignores writes to `$2000`, `$2001`, `$2005` and `$2006` until the end of the first
+/// frame after power-on, as on the console.
+#[derive(Clone)]
+pub struct PpuSimulation {
+    sim: SimulationState,
+    script: RegisterScript,
+    next_command: usize,
+    access: Access,
+    /// Half-steps since the current access began.
+    access_half_steps: u8,
+    prev_position: (u16, u16),
+    access_position: (u16, u16),
+    reads: Vec<RecordedRead>,
+}
+
+impl PpuSimulation {
+    pub fn new(script: RegisterScript) -> Self {
+        let mut sim = SimulationState::with_netlist(Arc::new(Netlist::load_chips(Chips::Ppu)));
+        sim.set_high(NODE_CPU_RW);
+        sim.init(false);
+        PpuSimulation {
+            sim,
+            script,
+            next_command: 0,
+            access: Access::Idle,
+            access_half_steps: 0,
+            prev_position: (u16::MAX, u16::MAX),
+            access_position: (0, 0),
+            reads: Vec::new(),
+        }
+    }
+
+    /// Load the cartridge's CHR and mirroring. This powers the PPU on again.
+    pub fn load_rom<R: Read + Seek>(&mut self, input: &mut R) {
+        self.sim.load_rom(input);
+    }
+
+    pub fn half_step(&mut self) {
+        let position = (self.sim.read_vpos(), self.sim.read_hpos());
+        self.drive_script(position);
+        self.prev_position = position;
+        self.sim.half_step();
+    }
+
+    /// Run until every command in the script has been carried out.
+    pub fn run_script(&mut self) {
+        while !self.is_script_finished() {
+            self.half_step();
+        }
+    }
+
+    pub fn is_script_finished(&self) -> bool {
+        self.next_command == self.script.commands.len()
+    }
+
+    pub fn reads(&self) -> &[RecordedRead] {
+        &self.reads
+    }
+
+    /// The events logged since `set_event_logging` turned logging on.
+    pub fn event_log(&self) -> Option<&PpuEventLog> {
+        self.sim.event_log()
+    }
+
+    pub fn set_event_logging(&mut self, enabled: bool) {
+        self.sim.set_event_logging(enabled);
+    }
+
+    pub fn framebuffer(&self) -> &[u32] {
+        &self.sim.ppu_framebuffer[..]
+    }
+
+    /// The pixels output so far as 9-bit values, when the output mode includes them.
+    pub fn raw_framebuffer(&self) -> &[u16] {
+        self.sim.raw_framebuffer()
+    }
+
+    pub fn set_output_mode(&mut self, mode: OutputMode) {
+        self.sim.set_output_mode(mode);
+    }
+
+    /// The PPU's vertical and horizontal counters.
+    pub fn position(&self) -> (u16, u16) {
+        (self.sim.read_vpos(), self.sim.read_hpos())
+    }
+
+    fn drive_script(&mut self, position: (u16, u16)) {
+        if let Access::Idle = self.access {
+            self.start_due_command(position);
+            return;
+        }
+
+        self.access_half_steps += 1;
+        match self.access {
+            Access::Selected if self.access_half_steps == IO_CE_LOW_HALF_STEPS => {
+                let command = self.script.commands[self.next_command];
+                if let RegisterAccess::Read { addr, record: true } = command.access {
+                    let (scanline, dot) = self.access_position;
+                    let val = self.sim.read_cpu_data_bus();
+                    self.reads.push(RecordedRead {
+                        scanline,
+                        dot,
+                        addr,
+                        val,
+                    });
+                }
+                self.sim.set_high(NODE_IO_CE);
+                self.access = Access::Releasing;
+            }
+            Access::Releasing => {
+                self.sim.float_cpu_db();
+                self.sim.set_high(NODE_CPU_RW);
+                self.access = Access::Released;
+            }
+            Access::Released if self.access_half_steps == CPU_CYCLE_HALF_STEPS => {
+                self.next_command += 1;
+                self.access = Access::Idle;
+                self.start_due_command(position);
+            }
+            _ => {}
+        }
+    }
+
+    fn start_due_command(&mut self, position: (u16, u16)) {
+        let command = match self.script.commands.get(self.next_command) {
+            Some(command) => *command,
+            None => return,
+        };
+
+        let is_due = match command.position {
+            Some(target) => position == target && self.prev_position != target,
+            None => true,
+        };
+        if is_due {
+            self.access_position = position;
+            self.access_half_steps = 0;
+            self.begin_access(command.access);
+        }
+    }
+
+    fn begin_access(&mut self, access: RegisterAccess) {
+        let addr = match access {
+            RegisterAccess::Read { addr, .. } => {
+                self.sim.set_high(NODE_CPU_RW);
+                addr
+            }
+            RegisterAccess::Write { addr, val } => {
+                self.sim.set_low(NODE_CPU_RW);
+                self.sim.write_cpu_db(val);
+                addr
+            }
+        };
+
+        for (i, node_number) in CPU_AB_NODES.iter().take(3).enumerate() {
+            if addr & (1 << i) == 0 {
+                self.sim.set_low(*node_number);
+            } else {
+                self.sim.set_high(*node_number);
+            }
+        }
+
+        self.sim.set_low(NODE_IO_CE);
+        self.access = Access::Selected;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_parse_positions_and_accesses() {
+        let script = RegisterScript::parse(
+            "# Turn rendering on\n\
+             at dot 0 of scanline 0 write $2001=$1E\n\
+             \n\
+             at dot 1 of scanline 241 read $2002 and record\n\
+             read 0x2004\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                ScriptCommand {
+                    position: Some((0, 0)),
+                    access: RegisterAccess::Write {
+                        addr: 0x2001,
+                        val: 0x1e
+                    },
+                },
+                ScriptCommand {
+                    position: Some((241, 1)),
+                    access: RegisterAccess::Read {
+                        addr: 0x2002,
+                        record: true
+                    },
+                },
+                ScriptCommand {
+                    position: None,
+                    access: RegisterAccess::Read {
+                        addr: 0x2004,
+                        record: false
+                    },
+                },
+            ],
+            script.commands
+        );
+    }
+
+    #[test]
+    fn script_errors_name_the_line() {
+        let error =
+            RegisterScript::parse("read $2002\nat dot 341 of scanline 0 read $2002").unwrap_err();
+        assert_eq!(2, error.line);
+
+        let error = RegisterScript::parse("write $0800=$00").unwrap_err();
+        assert_eq!("line 1: $0800 isn't a PPU register", error.to_string());
+    }
+}
@@ -4,9 +4,9 @@ mod tests;
 use crate::{
     components::{NodeDefinition, Transistor, TransistorDefinition},
     consts::{EMPTYNODE, NODE_GND, NODE_PWR},
+    netlist::{Chips, Region},
 };
 use std::{
-    cell::Cell,
     collections::HashMap,
     fs::File,
     io::{BufRead, BufReader, Read},
@@ -45,7 +45,11 @@ pub fn convert_id(id: u16, conversion_table: &HashMap<u16, u16>) -> u16 {
     *conversion_table.get(&id).unwrap_or(&id)
 }
 
-pub fn load_segment_definitions(conversion_table: &HashMap<u16, u16>) -> Vec<Vec<u16>> {
+pub fn load_segment_definitions(
+    chips: Chips,
+    region: Region,
+    conversion_table: &HashMap<u16, u16>,
+) -> Vec<Vec<u16>> {
     fn load_from_file<R: Read>(
         reader: R,
         segment_id_offset: u16,
@@ -73,18 +77,23 @@ pub fn load_segment_definitions(conversion_table: &HashMap<u16, u16>) -> Vec<Vec
             .collect::<Vec<Vec<u16>>>()
     }
 
-    let mut seg_defs = load_from_file(File::open("data/segdefs.txt").unwrap(), 0, conversion_table);
+    let mut seg_defs = Vec::new();
+    if chips.has_ppu() {
+        let path = format!("{}/segdefs.txt", region.ppu_data_dir());
+        seg_defs = load_from_file(File::open(path).unwrap(), 0, conversion_table);
+    }
 
-    let cpu_seg_defs = load_from_file(
-        File::open("data/cpusegdefs.txt").unwrap(),
-        CPU_OFFSET,
-        conversion_table,
-    );
+    if chips.has_cpu() {
+        let path = format!("{}/cpusegdefs.txt", region.cpu_data_dir());
+        let cpu_seg_defs = load_from_file(File::open(path).unwrap(), CPU_OFFSET, conversion_table);
 
-    seg_defs.extend(cpu_seg_defs);
+        seg_defs.extend(cpu_seg_defs);
+    }
     seg_defs
 }
 pub fn load_transistor_definitions(
+    chips: Chips,
+    region: Region,
     conversion_table: &HashMap<u16, u16>,
 ) -> Vec<TransistorDefinition> {
     fn load_from_file<R: Read>(
@@ -120,21 +129,23 @@ pub fn load_transistor_definitions(
             .collect()
     }
 
-    let mut trans_defs = load_from_file(
-        File::open("data/transdefs.txt").unwrap(),
-        "",
-        0,
-        conversion_table,
-    );
+    let mut trans_defs = Vec::new();
+    if chips.has_ppu() {
+        let path = format!("{}/transdefs.txt", region.ppu_data_dir());
+        trans_defs = load_from_file(File::open(path).unwrap(), "", 0, conversion_table);
+    }
 
-    let cpu_transistor_defs = load_from_file(
-        File::open("data/cputransdefs.txt").unwrap(),
-        "cpu_",
-        CPU_OFFSET,
-        conversion_table,
-    );
+    if chips.has_cpu() {
+        let path = format!("{}/cputransdefs.txt", region.cpu_data_dir());
+        let cpu_transistor_defs = load_from_file(
+            File::open(path).unwrap(),
+            "cpu_",
+            CPU_OFFSET,
+            conversion_table,
+        );
 
-    trans_defs.extend(cpu_transistor_defs);
+        trans_defs.extend(cpu_transistor_defs);
+    }
     trans_defs
 }
 
@@ -184,7 +195,7 @@ pub fn load_node_number_by_name_map(conversion_table: &HashMap<u16, u16>) -> Has
 }
 
 #[allow(clippy::type_complexity)]
-pub fn load_ppu_nodes() -> (Vec<Vec<(i32, i32)>>, Vec<Vec<(i32, i32)>>) {
+pub fn load_ppu_nodes(region: Region) -> (Vec<Vec<(i32, i32)>>, Vec<Vec<(i32, i32)>>) {
     fn load_from_file<R: Read>(reader: R) -> Vec<Vec<(i32, i32)>> {
         BufReader::new(reader)
             .lines()
@@ -203,8 +214,10 @@ pub fn load_ppu_nodes() -> (Vec<Vec<(i32, i32)>>, Vec<Vec<(i32, i32)>>) {
             .collect()
     }
 
-    let palette_nodes = load_from_file(File::open("data/palettenodes.txt").unwrap());
-    let sprite_nodes = load_from_file(File::open("data/spritenodes.txt").unwrap());
+    let palette_nodes =
+        load_from_file(File::open(format!("{}/palettenodes.txt", region.ppu_data_dir())).unwrap());
+    let sprite_nodes =
+        load_from_file(File::open(format!("{}/spritenodes.txt", region.ppu_data_dir())).unwrap());
 
     (palette_nodes, sprite_nodes)
 }
@@ -244,7 +257,7 @@ pub fn setup_nodes(segdefs: &[Vec<u16>]) -> Vec<NodeDefinition> {
             j += 2;
         }
 
-        nodes[w_idx].area += area.abs() as u64;
+        nodes[w_idx].area += area.unsigned_abs();
         nodes[w_idx].segs.push((seg[3], *seg.last().unwrap()))
     }
     nodes
@@ -252,7 +265,7 @@ pub fn setup_nodes(segdefs: &[Vec<u16>]) -> Vec<NodeDefinition> {
 
 #[allow(clippy::type_complexity)]
 pub fn setup_transistors(
-    nodes: &mut Vec<NodeDefinition>,
+    nodes: &mut [NodeDefinition],
     trans_defs: Vec<TransistorDefinition>,
 ) -> (
     Vec<Transistor>,
@@ -293,11 +306,7 @@ pub fn setup_transistors(
             node_counts[c2 as usize] += 1;
         }
 
-        transistors.push(Transistor {
-            c1,
-            c2,
-            on: Cell::new(false),
-        });
+        transistors.push(Transistor { c1, c2 });
         transistor_index_by_name.insert(name, i as u16);
     }
 
@@ -15,9 +15,9 @@ fn string_from_zip(file: &str) -> String {
 fn conversion_table_reference_test() {
     let reference_data = string_from_zip("test_data/conversion_table_reference.zip");
     let conversion_table = id_conversion_table();
-    let mut conversion_table: Vec<(u16, u16)> = conversion_table.into_iter().map(|v| v).collect();
+    let mut conversion_table: Vec<(u16, u16)> = conversion_table.into_iter().collect();
 
-    conversion_table.sort_by(|(a1, _), (a2, _)| a1.cmp(a2));
+    conversion_table.sort_by_key(|(a, _)| *a);
 
     let processed_data = conversion_table
         .iter()
@@ -32,7 +32,7 @@ fn conversion_table_reference_test() {
 fn segment_definitions_reference_test() {
     let reference_data = string_from_zip("test_data/segment_definitions_reference.zip");
     let conversion_table = id_conversion_table();
-    let seg_defs = load_segment_definitions(&conversion_table);
+    let seg_defs = load_segment_definitions(Chips::Both, Region::Ntsc, &conversion_table);
 
     let processed_data = seg_defs
         .iter()
@@ -51,7 +51,7 @@ fn segment_definitions_reference_test() {
 fn transistor_definition_reference_test() {
     let reference_data = string_from_zip("test_data/transistor_definition_reference.zip");
     let conversion_table = id_conversion_table();
-    let mut trans_defs = load_transistor_definitions(&conversion_table);
+    let mut trans_defs = load_transistor_definitions(Chips::Both, Region::Ntsc, &conversion_table);
 
     trans_defs.sort_by(|td1, td2| td1.name.cmp(&td2.name));
 
@@ -83,7 +83,7 @@ fn node_names_reference_test() {
 #[test]
 fn sprite_nodes_reference_test() {
     let reference_data = string_from_zip("test_data/sprite_nodes_reference.zip");
-    let (_, sprite_nodes) = load_ppu_nodes();
+    let (_, sprite_nodes) = load_ppu_nodes(Region::Ntsc);
 
     let processed_data = sprite_nodes
         .iter()
@@ -101,7 +101,7 @@ fn sprite_nodes_reference_test() {
 #[test]
 fn palette_nodes_reference_test() {
     let reference_data = string_from_zip("test_data/palette_nodes_reference.zip");
-    let (palette_nodes, _) = load_ppu_nodes();
+    let (palette_nodes, _) = load_ppu_nodes(Region::Ntsc);
 
     let processed_data = palette_nodes
         .iter()
@@ -120,23 +120,25 @@ fn palette_nodes_reference_test() {
 fn transistors_reference_test() {
     let reference_data = string_from_zip("test_data/transistors_reference.zip");
     let conversion_table = id_conversion_table();
-    let seg_defs = load_segment_definitions(&conversion_table);
-    let trans_defs = load_transistor_definitions(&conversion_table);
+    let seg_defs = load_segment_definitions(Chips::Both, Region::Ntsc, &conversion_table);
+    let trans_defs = load_transistor_definitions(Chips::Both, Region::Ntsc, &conversion_table);
     let mut nodes = setup_nodes(&seg_defs);
 
     let (transistors, ..) = setup_transistors(&mut nodes, trans_defs.clone());
+    let sim = crate::SimulationState::new();
 
     let processed_data = trans_defs
         .iter()
         .zip(transistors)
-        .map(|(trans_def, trans)| {
+        .enumerate()
+        .map(|(i, (trans_def, trans))| {
             format!(
                 "{},{},{},{},{}",
                 trans_def.name,
                 trans.c1,
                 trans.c2,
                 trans_def.gate,
-                if trans.on.get() { 1 } else { 0 }
+                if sim.transistor_on.get(i) { 1 } else { 0 }
             )
         })
         .collect::<Vec<String>>()
@@ -148,7 +150,7 @@ fn transistors_reference_test() {
 fn node_area_reference_test() {
     let reference_data = string_from_zip("test_data/node_area_reference.zip");
     let conversion_table = id_conversion_table();
-    let seg_defs = load_segment_definitions(&conversion_table);
+    let seg_defs = load_segment_definitions(Chips::Both, Region::Ntsc, &conversion_table);
     let nodes = setup_nodes(&seg_defs);
 
     let processed_data = nodes
@@ -163,8 +165,8 @@ fn node_area_reference_test() {
 fn node_counts_reference_test() {
     let reference_data = string_from_zip("test_data/node_counts_reference.zip");
     let conversion_table = id_conversion_table();
-    let seg_defs = load_segment_definitions(&conversion_table);
-    let trans_defs = load_transistor_definitions(&conversion_table);
+    let seg_defs = load_segment_definitions(Chips::Both, Region::Ntsc, &conversion_table);
+    let trans_defs = load_transistor_definitions(Chips::Both, Region::Ntsc, &conversion_table);
     let mut nodes = setup_nodes(&seg_defs);
 
     let (_, node_counts, ..) = setup_transistors(&mut nodes, trans_defs);
@@ -181,8 +183,8 @@ fn node_counts_reference_test() {
 fn nodes_c1_c2_reference_test() {
     let reference_data = string_from_zip("test_data/nodes_c1_c2_reference.zip");
     let conversion_table = id_conversion_table();
-    let seg_defs = load_segment_definitions(&conversion_table);
-    let trans_defs = load_transistor_definitions(&conversion_table);
+    let seg_defs = load_segment_definitions(Chips::Both, Region::Ntsc, &conversion_table);
+    let trans_defs = load_transistor_definitions(Chips::Both, Region::Ntsc, &conversion_table);
     let mut nodes = setup_nodes(&seg_defs);
     let (_, _, nodes_c1_c2, _) = setup_transistors(&mut nodes, trans_defs);
 
@@ -205,13 +207,13 @@ fn nodes_c1_c2_reference_test() {
 fn transistor_index_by_name_reference_test() {
     let reference_data = string_from_zip("test_data/transistor_index_by_name_reference.zip");
     let conversion_table = id_conversion_table();
-    let seg_defs = load_segment_definitions(&conversion_table);
-    let trans_defs = load_transistor_definitions(&conversion_table);
+    let seg_defs = load_segment_definitions(Chips::Both, Region::Ntsc, &conversion_table);
+    let trans_defs = load_transistor_definitions(Chips::Both, Region::Ntsc, &conversion_table);
     let mut nodes = setup_nodes(&seg_defs);
     let (_, _, _, transistor_index_by_name) = setup_transistors(&mut nodes, trans_defs);
 
     let mut transistor_index_by_name: Vec<(String, u16)> =
-        transistor_index_by_name.into_iter().map(|v| v).collect();
+        transistor_index_by_name.into_iter().collect();
 
     transistor_index_by_name.sort_by(|(a1, _b1), (a2, _b2)| a1.cmp(a2));
 
@@ -227,7 +229,7 @@ fn transistor_index_by_name_reference_test() {
 fn node_constant_tests() {
     // Ensure that the NUM_NODES constant always reflects the number of processed nodes.
     let conversion_table = id_conversion_table();
-    let seg_defs = load_segment_definitions(&conversion_table);
+    let seg_defs = load_segment_definitions(Chips::Both, Region::Ntsc, &conversion_table);
     let nodes = setup_nodes(&seg_defs);
     let node_number_by_name_map = load_node_number_by_name_map(&conversion_table);
 
@@ -488,6 +490,18 @@ fn node_constant_tests() {
         node_number_by_name_map["pal_d5_out"], NODE_PAL_D5_OUT,
         "Wrong PAL_D5_OUT constant value"
     );
+    assert_eq!(
+        node_number_by_name_map["/emph0_out"], NODE_NOT_EMPH0_OUT,
+        "Wrong NOT_EMPH0_OUT constant value"
+    );
+    assert_eq!(
+        node_number_by_name_map["/emph1_out"], NODE_NOT_EMPH1_OUT,
+        "Wrong NOT_EMPH1_OUT constant value"
+    );
+    assert_eq!(
+        node_number_by_name_map["/emph2_out"], NODE_NOT_EMPH2_OUT,
+        "Wrong NOT_EMPH2_OUT constant value"
+    );
     assert_eq!(
         node_number_by_name_map["pclk1"], NODE_PCLK1,
         "Wrong PCLK1 constant value"
@@ -1,53 +1,66 @@
-use std::{
-    cell::{Ref, RefCell},
-    rc::Rc,
+use crate::{
+    consts::{NODE_GND, NODE_PWR, NUM_NODES},
+    processed_nodes_map::ProcessedNodesSet,
 };
+use std::mem;
 
+#[derive(Clone)]
 pub struct RecalcSwapList {
-    lists: [Rc<RefCell<Vec<u16>>>; 2],
-    indices: (u8, u8),
+    cur_list: Vec<u16>,
+    next_list: Vec<u16>,
+    processed_nodes: ProcessedNodesSet,
 }
 
 impl RecalcSwapList {
     pub fn new() -> Self {
         RecalcSwapList {
-            lists: [
-                Rc::new(RefCell::new(Vec::with_capacity(14330))), // init() recalculates all nodes
-                Rc::new(RefCell::new(Vec::with_capacity(5120))),
-            ],
-            indices: (0, 1),
+            cur_list: Vec::with_capacity(14330), // init() recalculates all nodes
+            next_list: Vec::with_capacity(5120),
+            processed_nodes: ProcessedNodesSet::new(NUM_NODES),
         }
     }
 
     pub fn init(&mut self, nodes: &[u16]) {
-        self.lists[0].borrow_mut().clear();
-        self.lists[0].borrow_mut().extend_from_slice(nodes);
-        self.indices = (0, 1);
+        self.cur_list.clear();
+        self.cur_list.extend_from_slice(nodes);
     }
 
-    pub fn cur_list(&self) -> Rc<RefCell<Vec<u16>>> {
-        let (cur_list, _) = self.indices;
-        self.lists[cur_list as usize].clone()
+    #[inline]
+    pub fn cur_len(&self) -> usize {
+        self.cur_list.len()
     }
 
-    pub fn push_next_list(&self, node: u16) {
-        let (_, next_list) = self.indices;
-        self.lists[next_list as usize].borrow_mut().push(node);
+    #[cfg(feature = "parallel")]
+    pub fn cur_list(&self) -> &[u16] {
+        &self.cur_list
     }
 
-    pub fn next_list(&self) -> Ref<Vec<u16>> {
-        let (_, next_list) = self.indices;
-        self.lists[next_list as usize].borrow()
+    #[inline]
+    pub fn cur_node(&self, index: usize) -> u16 {
+        self.cur_list[index]
+    }
+
+    /// Queue a node for the next iteration. Power, ground and nodes that are already queued are
+    /// ignored.
+    #[inline]
+    pub fn push_next_list(&mut self, node: u16) {
+        if node == NODE_GND || node == NODE_PWR {
+            return;
+        }
+
+        if !self.processed_nodes.contains(node) {
+            self.next_list.push(node);
+            self.processed_nodes.set(node);
+        }
     }
 
     pub fn is_next_list_empty(&self) -> bool {
-        let (_, next_list) = self.indices;
-        self.lists[next_list as usize].borrow().is_empty()
+        self.next_list.is_empty()
     }
 
     pub fn swap(&mut self) {
-        let (cur_list, next_list) = self.indices;
-        self.lists[cur_list as usize].borrow_mut().clear();
-        self.indices = (next_list, cur_list);
+        self.processed_nodes.clear(&self.next_list);
+        self.cur_list.clear();
+        mem::swap(&mut self.cur_list, &mut self.next_list);
     }
 }
@@ -0,0 +1,246 @@
+use crate::{
+    image::{png_header, png_scanlines, write_png_chunk, zlib_stored, Crop, PNG_SIGNATURE},
+    SimulationState,
+};
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// The file a `FrameRecorder` writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// Uncompressed YUV4MPEG2 video with 4:4:4 BT.601 colour, which most video tools read.
+    Y4m,
+    /// An animated PNG, which keeps the colours exact and plays in a browser.
+    Apng,
+}
+
+/// Which frames a `FrameRecorder` keeps and how.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecorderOptions {
+    pub format: RecordingFormat,
+    pub crop: Crop,
+    /// The number of frames to skip after each one recorded.
+    pub frame_skip: u64,
+    /// The first frame to record, counting frames completed since power-on from 1 as
+    /// `SimulationState::frame_count` does.
+    pub first_frame: u64,
+    /// The frame to stop before, if any.
+    pub end_frame: Option<u64>,
+    /// The rate frames are output at, as a numerator and denominator in frames per second.
+    pub frame_rate: (u32, u32),
+}
+
+impl Default for RecorderOptions {
+    fn default() -> Self {
+        RecorderOptions {
+            format: RecordingFormat::Y4m,
+            crop: Crop::NONE,
+            frame_skip: 0,
+            first_frame: 1,
+            end_frame: None,
+            // The NTSC console's 60.0988 frames per second.
+            frame_rate: (39_375_000, 655_171),
+        }
+    }
+}
+
+/// Records the frames a simulation completes to a video or animated image.
+///
+/// Call `capture` after every `half_step`, and it picks out the half-steps that complete a frame
+/// and keeps the ones the options select. The file isn't complete until `finish` is called.
+pub struct FrameRecorder<W: Write + Seek> {
+    writer: W,
+    options: RecorderOptions,
+    frames_recorded: u32,
+    /// Where the APNG's animation control chunk is, so its frame count can be filled in when the
+    /// recording is finished.
+    apng_control_pos: u64,
+}
+
+impl<W: Write + Seek> FrameRecorder<W> {
+    pub fn new(mut writer: W, options: RecorderOptions) -> io::Result<Self> {
+        let (width, height) = (options.crop.width(), options.crop.height());
+        let (rate, scale) = options.frame_rate;
+        let mut apng_control_pos = 0;
+        match options.format {
+            RecordingFormat::Y4m => writeln!(
+                writer,
+                "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                width,
+                height,
+                rate,
+                u64::from(scale) * (options.frame_skip + 1)
+            )?,
+            RecordingFormat::Apng => {
+                writer.write_all(&PNG_SIGNATURE)?;
+                write_png_chunk(&mut writer, b"IHDR", &png_header(width, height))?;
+                // The frame count is filled in by `finish`.
+                apng_control_pos = writer.stream_position()?;
+                write_png_chunk(&mut writer, b"acTL", &[0; 8])?;
+            }
+        }
+        Ok(FrameRecorder {
+            writer,
+            options,
+            frames_recorded: 0,
+            apng_control_pos,
+        })
+    }
+
+    pub fn frames_recorded(&self) -> u32 {
+        self.frames_recorded
+    }
+
+    /// Record the framebuffer if the last half-step completed a frame the options select.
+    /// Returns whether it did.
+    pub fn capture(&mut self, sim: &SimulationState) -> io::Result<bool> {
+        if sim.is_frame_completed() {
+            self.record(sim.frame_count(), sim.framebuffer())
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Record a 256x240 ARGB frame if the options select its number. Returns whether it did.
+    pub fn record(&mut self, number: u64, frame: &[u32]) -> io::Result<bool> {
+        let options = &self.options;
+        if number < options.first_frame || options.end_frame.is_some_and(|end| number >= end) {
+            return Ok(false);
+        }
+        let frames_since_recorded = (number - options.first_frame) % (options.frame_skip + 1);
+        if frames_since_recorded > 0 {
+            return Ok(false);
+        }
+
+        let pixels = options.crop.apply(frame);
+        match options.format {
+            RecordingFormat::Y4m => self.write_y4m_frame(&pixels)?,
+            RecordingFormat::Apng => self.write_apng_frame(&pixels)?,
+        }
+        self.frames_recorded += 1;
+        Ok(true)
+    }
+
+    /// Finish the file and hand back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.options.format == RecordingFormat::Apng {
+            write_png_chunk(&mut self.writer, b"IEND", &[])?;
+            let end = self.writer.stream_position()?;
+            let mut control = self.frames_recorded.to_be_bytes().to_vec();
+            // Loop forever.
+            control.extend_from_slice(&[0; 4]);
+            self.writer.seek(SeekFrom::Start(self.apng_control_pos))?;
+            write_png_chunk(&mut self.writer, b"acTL", &control)?;
+            self.writer.seek(SeekFrom::Start(end))?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_y4m_frame(&mut self, pixels: &[u32]) -> io::Result<()> {
+        let mut planes = vec![0; pixels.len() * 3];
+        let (y, uv) = planes.split_at_mut(pixels.len());
+        let (u, v) = uv.split_at_mut(pixels.len());
+        for (i, pixel) in pixels.iter().enumerate() {
+            let r = ((pixel >> 16) & 0xff) as i32;
+            let g = ((pixel >> 8) & 0xff) as i32;
+            let b = (pixel & 0xff) as i32;
+            y[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+            u[i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            v[i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&planes)
+    }
+
+    fn write_apng_frame(&mut self, pixels: &[u32]) -> io::Result<()> {
+        let options = &self.options;
+        let (rate, scale) = options.frame_rate;
+        let delay = reduce_to_u16(u64::from(scale) * (options.frame_skip + 1), u64::from(rate));
+        // The frame control and frame data chunks are numbered in one sequence, except for the
+        // first frame's data, which is an ordinary IDAT chunk.
+        let sequence = (self.frames_recorded * 2).saturating_sub(1);
+
+        let mut control = Vec::with_capacity(26);
+        control.extend_from_slice(&sequence.to_be_bytes());
+        control.extend_from_slice(&(options.crop.width() as u32).to_be_bytes());
+        control.extend_from_slice(&(options.crop.height() as u32).to_be_bytes());
+        control.extend_from_slice(&[0; 8]);
+        control.extend_from_slice(&delay.0.to_be_bytes());
+        control.extend_from_slice(&delay.1.to_be_bytes());
+        control.extend_from_slice(&[0, 0]);
+        write_png_chunk(&mut self.writer, b"fcTL", &control)?;
+
+        let image = zlib_stored(&png_scanlines(pixels, options.crop.width()));
+        if self.frames_recorded == 0 {
+            // The first frame doubles as the still image for viewers that don't animate.
+            write_png_chunk(&mut self.writer, b"IDAT", &image)
+        } else {
+            let mut data = Vec::with_capacity(image.len() + 4);
+            data.extend_from_slice(&(sequence + 1).to_be_bytes());
+            data.extend_from_slice(&image);
+            write_png_chunk(&mut self.writer, b"fdAT", &data)
+        }
+    }
+}
+
+/// Scale a fraction down until it fits an APNG frame delay.
+fn reduce_to_u16(numerator: u64, denominator: u64) -> (u16, u16) {
+    let max = u64::from(u16::MAX);
+    let largest = numerator.max(denominator);
+    if largest <= max {
+        return (numerator as u16, denominator.max(1) as u16);
+    }
+    let scale = |n: u64| ((n * max + largest / 2) / largest) as u16;
+    (scale(numerator), scale(denominator).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{FRAME_HEIGHT, FRAME_WIDTH};
+    use std::io::Cursor;
+
+    fn record_frames(options: RecorderOptions) -> (u32, Vec<u8>) {
+        let mut recorder = FrameRecorder::new(Cursor::new(Vec::new()), options).unwrap();
+        for number in 1..=10 {
+            let frame = vec![0xff00_0000 | number as u32; FRAME_WIDTH * FRAME_HEIGHT];
+            recorder.record(number, &frame).unwrap();
+        }
+        let frames = recorder.frames_recorded();
+        (frames, recorder.finish().unwrap().into_inner())
+    }
+
+    #[test]
+    fn y4m_recordings_keep_the_selected_frames() {
+        let (frames, y4m) = record_frames(RecorderOptions {
+            crop: Crop::OVERSCAN,
+            frame_skip: 1,
+            first_frame: 3,
+            end_frame: Some(9),
+            frame_rate: (60, 1),
+            ..RecorderOptions::default()
+        });
+        // Frames 3, 5 and 7.
+        assert_eq!(3, frames);
+        let header = b"YUV4MPEG2 W240 H224 F60:2 Ip A1:1 C444\n";
+        assert!(y4m.starts_with(header));
+        assert_eq!(header.len() + 3 * (6 + 240 * 224 * 3), y4m.len());
+    }
+
+    #[test]
+    fn apng_recordings_count_their_frames() {
+        let (frames, apng) = record_frames(RecorderOptions {
+            format: RecordingFormat::Apng,
+            ..RecorderOptions::default()
+        });
+        assert_eq!(10, frames);
+        // The acTL chunk follows the 8-byte signature and the 25-byte IHDR chunk.
+        assert_eq!(b"acTL", &apng[37..41]);
+        assert_eq!(
+            10,
+            u32::from_be_bytes([apng[41], apng[42], apng[43], apng[44]])
+        );
+        assert!(apng.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+        assert_eq!((1090, 65535), reduce_to_u16(655_171, 39_375_000));
+    }
+}
@@ -0,0 +1,176 @@
+use std::io::{self, Write};
+
+/// The work done by the engine over some span of the simulation.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct RecalcCounts {
+    /// Nodes taken from a recalc list and resolved.
+    pub nodes_recalculated: u64,
+    /// Iterations of `recalc_node_list` until the nodes settled.
+    pub iterations: u64,
+    pub transistor_toggles: u64,
+}
+
+/// Counters describing what the engine spends its time on, per half-step and in total.
+///
+/// By default only the counts of the last half-step are kept per half-step, in `last_half_step`.
+/// Keeping the counts of every half-step in `half_steps` is opt-in, with `set_half_step_recording`,
+/// as it grows by 24 bytes a half-step, some 17 MB a frame. The totals, group size histogram and
+/// per-node counts are always kept, and also include work done outside of half-steps, such as the
+/// recalc of the whole chip at power-on.
+#[derive(Clone)]
+pub struct SimulationStats {
+    /// The counts of every half-step since the stats were last reset, when recording is on.
+    pub half_steps: Vec<RecalcCounts>,
+    /// The number of half-steps since the stats were last reset.
+    pub half_step_count: u64,
+    pub total: RecalcCounts,
+    record_half_steps: bool,
+    current: RecalcCounts,
+    last_half_step: RecalcCounts,
+    /// The number of groups resolved with each size, indexed by size.
+    group_sizes: Vec<u64>,
+    node_recalc_counts: Vec<u64>,
+}
+
+impl SimulationStats {
+    pub(crate) fn new(node_count: usize) -> Self {
+        SimulationStats {
+            half_steps: Vec::new(),
+            half_step_count: 0,
+            total: RecalcCounts::default(),
+            record_half_steps: false,
+            current: RecalcCounts::default(),
+            last_half_step: RecalcCounts::default(),
+            group_sizes: Vec::new(),
+            node_recalc_counts: vec![0; node_count],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.half_steps.clear();
+        self.half_step_count = 0;
+        self.total = RecalcCounts::default();
+        self.current = RecalcCounts::default();
+        self.last_half_step = RecalcCounts::default();
+        self.group_sizes.clear();
+        self.node_recalc_counts
+            .iter_mut()
+            .for_each(|count| *count = 0);
+    }
+
+    /// Turn on or off keeping the counts of each half-step in `half_steps`. It's off by default.
+    pub fn set_half_step_recording(&mut self, enabled: bool) {
+        self.record_half_steps = enabled;
+    }
+
+    /// The counts of the last half-step since the stats were last reset, whether or not
+    /// recording is on.
+    pub fn last_half_step(&self) -> RecalcCounts {
+        self.last_half_step
+    }
+
+    /// The number of groups resolved with each size, as (size, count) pairs in ascending order of
+    /// size.
+    pub fn group_size_histogram(&self) -> Vec<(usize, u64)> {
+        self.group_sizes
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(size, count)| (size, *count))
+            .collect()
+    }
+
+    /// How many times `node_number` was taken from a recalc list.
+    pub fn node_recalc_count(&self, node_number: u16) -> u64 {
+        self.node_recalc_counts[node_number as usize]
+    }
+
+    /// The `n` nodes recalculated most often, as (node, count) pairs from the most to the least
+    /// recalculated. Ties are ordered by node number.
+    pub fn hottest_nodes(&self, n: usize) -> Vec<(u16, u64)> {
+        let mut nodes = self
+            .node_recalc_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(node_number, count)| (node_number as u16, *count))
+            .collect::<Vec<(u16, u64)>>();
+        nodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        nodes.truncate(n);
+        nodes
+    }
+
+    pub fn write_summary_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "metric,value")?;
+        writeln!(out, "half_steps,{}", self.half_step_count)?;
+        writeln!(out, "nodes_recalculated,{}", self.total.nodes_recalculated)?;
+        writeln!(out, "iterations,{}", self.total.iterations)?;
+        writeln!(out, "transistor_toggles,{}", self.total.transistor_toggles)
+    }
+
+    pub fn write_half_steps_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(
+            out,
+            "half_step,nodes_recalculated,iterations,transistor_toggles"
+        )?;
+        for (i, counts) in self.half_steps.iter().enumerate() {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                i, counts.nodes_recalculated, counts.iterations, counts.transistor_toggles
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_group_sizes_csv<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "group_size,count")?;
+        for (size, count) in self.group_size_histogram() {
+            writeln!(out, "{},{}", size, count)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_hottest_nodes_csv<W: Write>(&self, out: &mut W, n: usize) -> io::Result<()> {
+        writeln!(out, "node,recalc_count")?;
+        for (node_number, count) in self.hottest_nodes(n) {
+            writeln!(out, "{},{}", node_number, count)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn begin_half_step(&mut self) {
+        self.current = RecalcCounts::default();
+    }
+
+    pub(crate) fn end_half_step(&mut self) {
+        self.half_step_count += 1;
+        self.last_half_step = self.current;
+        if self.record_half_steps {
+            self.half_steps.push(self.current);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn record_iteration(&mut self) {
+        self.current.iterations += 1;
+        self.total.iterations += 1;
+    }
+
+    #[inline]
+    pub(crate) fn record_recalc(&mut self, node_number: u16, group_size: usize) {
+        self.current.nodes_recalculated += 1;
+        self.total.nodes_recalculated += 1;
+        self.node_recalc_counts[node_number as usize] += 1;
+        if self.group_sizes.len() <= group_size {
+            self.group_sizes.resize(group_size + 1, 0);
+        }
+        self.group_sizes[group_size] += 1;
+    }
+
+    #[inline]
+    pub(crate) fn record_transistor_toggle(&mut self) {
+        self.current.transistor_toggles += 1;
+        self.total.transistor_toggles += 1;
+    }
+}
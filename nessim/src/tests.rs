@@ -1,12 +1,17 @@
-use crate::{MemoryType, SimulationState, NUM_NODES};
+use crate::{
+    ntsc, write_frame_png, BitSlicedSimulation, ClockAlignment, CpuSimulation, Crop, FastForward,
+    FrameRecorder, MemoryType, NtscPaletteOptions, OutputMode, PpuEventKind, PpuSimulation,
+    RamFill, RecorderOptions, Region, RegisterScript, SimulationState, NODE_CPU_CLK0, NODE_CPU_IRQ,
+    NODE_GND, NODE_PCLK1, NODE_PWR, NUM_NODES, PATTERN_TABLE_SIZE, SAMPLES_PER_DOT,
+    SUBCARRIER_PHASES,
+};
 use byteorder::{LittleEndian, ReadBytesExt};
-use std::io::Read;
+use std::{fs::File, io::Read, sync::Arc, thread};
 
 const NUM_TRANSISTORS: usize = 27703;
 
 #[test]
 fn reference_tests() {
-    use std::fs::File;
     let mut sim = SimulationState::new();
 
     let reader = File::open("test_data/reference_samples.zip").unwrap();
@@ -52,13 +57,841 @@ fn reference_tests() {
     }
 }
 
+#[test]
+fn simulation_state_is_send_and_clone() {
+    fn assert_send_and_clone<T: Send + Clone>() {}
+    assert_send_and_clone::<SimulationState>();
+}
+
+#[test]
+fn cloned_simulation_steps_independently_on_another_thread() {
+    let mut sim = scanline_sim(1000);
+
+    let mut clone = sim.clone();
+    let worker = thread::spawn(move || {
+        for _ in 0..1000 {
+            clone.half_step();
+        }
+        clone
+    });
+
+    run_half_steps(&mut sim, 1000);
+
+    let clone = worker.join().unwrap();
+    assert!(Arc::ptr_eq(&sim.netlist, &clone.netlist));
+    assert!(sim.node_state == clone.node_state);
+    assert!(sim.transistor_on == clone.transistor_on);
+    assert_eq!(&sim.ppu_framebuffer[..], &clone.ppu_framebuffer[..]);
+}
+
+#[test]
+fn forked_simulations_diverge_without_affecting_each_other() {
+    let sim = scanline_sim(100);
+
+    let mut fork = sim.clone();
+    fork.memory.cpu_write(0x0010, 0xa5);
+    fork.set_high(NODE_CPU_IRQ);
+    fork.set_low(NODE_CPU_IRQ);
+
+    assert_eq!(0, sim.memory.cpu_ram[0x10]);
+    assert_eq!(0xa5, fork.memory.cpu_ram[0x10]);
+    assert!(!sim.node_pulldown.get(NODE_CPU_IRQ as usize));
+    assert!(fork.node_pulldown.get(NODE_CPU_IRQ as usize));
+}
+
+#[test]
+fn bit_sliced_lanes_match_scalar_simulations() {
+    let mut sim = scanline_sim(200);
+    sim.set_event_logging(true);
+
+    // Give one lane a different clock phase so its groups diverge from the others, and settings
+    // of its own.
+    let mut shifted = sim.clone();
+    run_half_steps(&mut shifted, 37);
+    shifted.set_reset_hold_half_steps(100);
+    let mut options = shifted.power_on_options();
+    options.cpu_ram = RamFill::Ones;
+    shifted.set_power_on_options(options);
+
+    let mut bit_sliced = BitSlicedSimulation::from_simulation(&sim);
+    bit_sliced.set_lane(5, &shifted);
+
+    for _ in 0..2000 {
+        sim.half_step();
+        shifted.half_step();
+        bit_sliced.half_step();
+    }
+
+    verify_same_state(&sim, &bit_sliced.lane(0));
+    verify_same_state(&shifted, &bit_sliced.lane(5));
+    verify_same_state(&sim, &bit_sliced.lane(63));
+}
+
+#[test]
+fn fast_forward_runs_the_cartridge_behaviourally() {
+    let sim = scanline_sim(0);
+
+    let mut fast_forward = FastForward::new(&sim);
+    fast_forward.run_frames(10);
+    assert_eq!(10, fast_forward.frame());
+    // Frames are about 341 * 262 / 3 CPU cycles apart, give or take the odd frames' skipped dot.
+    assert!(fast_forward.cpu_cycles() >= 9 * 29780);
+}
+
+#[test]
+fn soft_reset_keeps_memory_and_restarts_the_cpu() {
+    let mut sim = scanline_sim(1000);
+    sim.memory.cpu_write(0x07ff, 0xa5);
+    sim.memory.nametable_ram[1][0x3ff] = 0x5a;
+
+    sim.reset();
+    assert_eq!(0xa5, sim.memory.cpu_ram[0x7ff]);
+    assert_eq!(0x5a, sim.memory.nametable_ram[1][0x3ff]);
+
+    // The 6502 spends seven cycles in its reset sequence before fetching the vector.
+    let fetches_vector = (0..24 * 10).any(|_| {
+        sim.half_step();
+        sim.read_cpu_address_bus() == 0xfffc
+    });
+    assert!(fetches_vector);
+}
+
+#[test]
+fn power_cycle_clears_memory_but_keeps_the_cartridge() {
+    let mut sim = scanline_sim(0);
+    let prg_ram = sim.memory.prg_ram.clone();
+    sim.memory.cpu_write(0x07ff, 0xa5);
+
+    sim.power_cycle();
+    assert_eq!(0, sim.memory.cpu_ram[0x7ff]);
+    assert_eq!(&prg_ram[..], &sim.memory.prg_ram[..]);
+}
+
+#[test]
+fn each_clock_alignment_powers_on_in_a_different_phase() {
+    // The half-step, within a PPU dot, on which the first CPU cycle after power-on begins.
+    fn cpu_phase(sim: &mut SimulationState) -> usize {
+        let mut cpu_start = None;
+        let mut dot_start = None;
+        let mut prev = (true, true);
+        for half_step in 0..48 {
+            sim.half_step();
+            let cur = (
+                sim.is_node_high(NODE_CPU_CLK0),
+                sim.is_node_high(NODE_PCLK1),
+            );
+            if cur.0 && !prev.0 && cpu_start.is_none() {
+                cpu_start = Some(half_step);
+            }
+            if cur.1 && !prev.1 && dot_start.is_none() {
+                dot_start = Some(half_step);
+            }
+            prev = cur;
+        }
+        (cpu_start.unwrap() + 8 - dot_start.unwrap() % 8) % 8
+    }
+
+    let mut sim = SimulationState::new();
+    let mut phases = Vec::new();
+    let alignments = sim.region().clock_alignments();
+    for alignment in 0..alignments {
+        let mut options = sim.power_on_options();
+        options.clock_alignment = ClockAlignment::Fixed(alignment);
+        sim.set_power_on_options(options);
+        sim.power_cycle();
+        assert_eq!(alignment, sim.clock_alignment());
+        phases.push(cpu_phase(&mut sim));
+    }
+
+    phases.sort();
+    phases.dedup();
+    assert_eq!(alignments as usize, phases.len());
+}
+
+#[test]
+fn power_on_options_fill_ram_and_randomise_nodes_reproducibly() {
+    let mut sim = SimulationState::new();
+    let mut options = sim.power_on_options();
+    options.cpu_ram = RamFill::Ones;
+    options.nametable_ram = RamFill::Alternating;
+    options.random_undriven_nodes = Some(1);
+    sim.set_power_on_options(options);
+    sim.load_rom(&mut File::open("test_data/scanline.nes").unwrap());
+
+    assert!(sim.memory.cpu_ram.iter().all(|b| *b == 0xff));
+    assert_eq!(0, sim.memory.nametable_ram[0][3]);
+    assert_eq!(0xff, sim.memory.nametable_ram[0][4]);
+
+    let mut again = sim.clone();
+    again.power_cycle();
+    assert!(sim.node_state == again.node_state);
+
+    options.random_undriven_nodes = Some(2);
+    again.set_power_on_options(options);
+    again.power_cycle();
+    assert!(sim.node_state != again.node_state);
+    assert_ne!(sim.palette_ram(), again.palette_ram());
+    assert_ne!(&sim.oam()[..], &again.oam()[..]);
+    // The latches outside the cells power on differently too.
+    let netlist = &sim.netlist;
+    let cells = netlist
+        .palette_nodes
+        .iter()
+        .chain(&netlist.sprite_nodes)
+        .flat_map(|bits| bits.iter().flat_map(|(n0, n1)| vec![*n0, *n1]))
+        .collect::<Vec<i32>>();
+    assert!(netlist.all_recalc_nodes.iter().any(|node_number| {
+        !cells.contains(&i32::from(*node_number))
+            && sim.node_state.get(*node_number as usize)
+                != again.node_state.get(*node_number as usize)
+    }));
+}
+
+#[test]
+fn regions_need_their_netlists() {
+    let ntsc = SimulationState::with_region(Region::Ntsc).unwrap();
+    assert_eq!(Region::Ntsc, ntsc.region());
+    assert_eq!(11, Region::Ntsc.io_ce_low_half_steps());
+    assert_eq!(14, Region::Pal.io_ce_low_half_steps());
+    assert_eq!(
+        [4, 1, 5],
+        [Region::Ntsc, Region::Pal, Region::Dendy].map(Region::clock_alignments)
+    );
+
+    // Only the NTSC chips are checked in.
+    let error = SimulationState::with_region(Region::Pal).err().unwrap();
+    assert_eq!(Region::Pal, error.region);
+    assert_eq!("data/2c07/segdefs.txt", error.path);
+    let error = SimulationState::with_region(Region::Dendy).err().unwrap();
+    assert_eq!("data/ua6538/segdefs.txt", error.path);
+}
+
+#[test]
+fn framebuffer_and_frame_count_start_empty_at_power_on() {
+    let mut sim = scanline_sim(0);
+    for _ in 0..100 {
+        sim.half_step();
+        assert!(!sim.is_frame_completed());
+    }
+    assert_eq!(0, sim.frame_count());
+    assert_eq!(256 * 240, sim.framebuffer().len());
+}
+
+#[test]
+fn raw_pixel_output_matches_the_argb_output() {
+    let mut sim = scanline_sim(0);
+    sim.set_output_mode(OutputMode::Both);
+    run_half_steps(&mut sim, 4000);
+
+    // The first line is being output by now.
+    let written = sim.raw_framebuffer()[..256]
+        .iter()
+        .zip(&sim.framebuffer()[..256])
+        .filter(|(_, argb)| **argb != 0)
+        .collect::<Vec<_>>();
+    assert!(!written.is_empty());
+    for (pixel, argb) in written {
+        assert_eq!(sim.palette().argb(*pixel), *argb);
+    }
+}
+
+#[test]
+fn composite_output_follows_the_pixels_and_the_subcarrier() {
+    let mut sim = scanline_sim(0);
+    sim.set_output_mode(OutputMode::RawPixels);
+    sim.set_composite_output(true);
+    run_half_steps(&mut sim, 4000);
+
+    let composite = sim.composite_frame().unwrap();
+    let samples = composite.samples().chunks(SAMPLES_PER_DOT);
+    for ((pixel, phase), samples) in sim.raw_framebuffer()[..200]
+        .iter()
+        .zip(composite.phases())
+        .zip(samples)
+    {
+        for (i, sample) in samples.iter().enumerate() {
+            let expected = ntsc::signal_level(*pixel, (*phase + i as u8) % SUBCARRIER_PHASES);
+            assert_eq!(expected, *sample);
+        }
+    }
+    // A dot is two thirds of a subcarrier cycle.
+    for dots in composite.phases()[..200].windows(2) {
+        assert_eq!((dots[0] + 8) % SUBCARRIER_PHASES, dots[1]);
+    }
+    assert_eq!(
+        256 * 240,
+        composite.decode(&NtscPaletteOptions::default()).len()
+    );
+}
+
+#[test]
+fn frames_are_saved_as_png_and_ppm() {
+    let sim = scanline_sim(4000);
+
+    let dir = std::env::temp_dir();
+    let png = dir.join(format!("nessim-frame-{}.png", std::process::id()));
+    let ppm = dir.join(format!("nessim-frame-{}.ppm", std::process::id()));
+    sim.save_frame_png(&png, Crop::NONE).unwrap();
+    sim.save_frame_ppm(&ppm, Crop::OVERSCAN).unwrap();
+
+    let mut expected = Vec::new();
+    write_frame_png(&mut expected, sim.framebuffer(), Crop::NONE).unwrap();
+    assert_eq!(expected, std::fs::read(&png).unwrap());
+    let ppm_bytes = std::fs::read(&ppm).unwrap();
+    assert_eq!(15 + 240 * 224 * 3, ppm_bytes.len());
+    // The first kept pixel of the first line.
+    let rgb = sim.framebuffer()[8 * 256 + 8];
+    assert_eq!(
+        [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8],
+        ppm_bytes[15..18]
+    );
+    std::fs::remove_file(png).unwrap();
+    std::fs::remove_file(ppm).unwrap();
+}
+
+#[test]
+fn recorder_only_captures_completed_frames() {
+    let mut sim = scanline_sim(0);
+    let mut recorder =
+        FrameRecorder::new(std::io::Cursor::new(Vec::new()), RecorderOptions::default()).unwrap();
+    for _ in 0..1000 {
+        sim.half_step();
+        assert!(!recorder.capture(&sim).unwrap());
+    }
+
+    // A copy of a completed frame can still be recorded by its number.
+    assert!(recorder.record(1, sim.framebuffer()).unwrap());
+    assert_eq!(1, recorder.frames_recorded());
+}
+
+#[test]
+fn viewers_read_the_palette_and_oam_from_their_cells() {
+    // The PPU connects a row of OAM at a time to its bit lines, which then hold the row's cells
+    // whatever is written to them. At power-on that's the last row of primary OAM and the last
+    // byte of secondary OAM, so write once the PPU has let go of them.
+    let powered_on = scanline_sim(0);
+    assert!((0xf8..0x100).all(|addr| is_oam_byte_on_bit_lines(&powered_on, addr)));
+    assert!(is_oam_byte_on_bit_lines(&powered_on, 0x11f));
+    let mut sim = scanline_sim(100);
+    assert!((0..0x120).all(|addr| !is_oam_byte_on_bit_lines(&sim, addr)));
+    let palette = (0..0x20_u8)
+        .map(|addr| {
+            if addr & 0x13 == 0x10 {
+                addr & 0x0f
+            } else {
+                addr
+            }
+        })
+        .collect::<Vec<u8>>();
+    let oam = (0..0x120_u32)
+        .map(|addr| (addr * 7) as u8)
+        .collect::<Vec<u8>>();
+    sim.set_memory_state(MemoryType::PaletteRam, &palette);
+    sim.set_memory_state(MemoryType::SpriteRam, &oam);
+
+    assert_eq!(&palette[..], &sim.palette_ram()[..]);
+    for (addr, (expected, actual)) in oam.iter().zip(sim.oam().iter()).enumerate() {
+        // Attribute bits 2-4 of primary OAM aren't stored.
+        let mask = if addr < 0x100 && addr & 3 == 2 {
+            0xe3
+        } else {
+            0xff
+        };
+        assert_eq!(expected & mask, *actual, "OAM byte {}", addr);
+    }
+
+    let pattern_table = sim.render_pattern_table(0, 1);
+    assert_eq!(
+        (PATTERN_TABLE_SIZE, PATTERN_TABLE_SIZE),
+        (pattern_table.width, pattern_table.height)
+    );
+    // Tile 1's top row, one pixel at a time.
+    let chr = &sim.memory.chr_ram;
+    for x in 0..8 {
+        let pixel = ((chr[16] >> (7 - x)) & 1) | (((chr[24] >> (7 - x)) & 1) << 1);
+        let entry = if pixel == 0 { 0 } else { 4 + pixel as usize };
+        assert_eq!(
+            sim.palette().argb(u16::from(palette[entry])),
+            pattern_table.pixel(8 + x, 0)
+        );
+    }
+
+    let nametables = sim.render_nametables(0);
+    assert_eq!((512, 480), (nametables.width, nametables.height));
+    assert_eq!(
+        (64, 64),
+        (sim.render_oam(0).width, sim.render_oam(0).height)
+    );
+    let palette_view = sim.render_palette();
+    assert_eq!(sim.palette().argb(0x1f), palette_view.pixel(15 * 8, 8));
+}
+
+#[test]
+fn ppu_only_script_reads_back_the_io_latch() {
+    // Any write fills the latch, which reads of $2002 return in their low five bits. The PPU
+    // misses accesses made in its first few dots after power-on.
+    let script = RegisterScript::parse(
+        "at dot 5 of scanline 0 write $2003=$15\n\
+         read $2002 and record\n",
+    )
+    .unwrap();
+    let mut ppu = PpuSimulation::new(script);
+    ppu.run_script();
+
+    let reads = ppu.reads();
+    assert_eq!(1, reads.len());
+    assert_eq!(0x2002, reads[0].addr);
+    assert_eq!(0x15, reads[0].val & 0x1f);
+}
+
+// The PPU has to run for a frame first, which takes most of a minute in release, so run this with
+// `cargo test --release emphasis_bits -- --ignored`.
+#[test]
+#[ignore]
+fn raw_pixels_carry_the_emphasis_bits_written_to_ppumask() {
+    // Wait for the end of the first frame, before which the PPU ignores writes to $2001, then
+    // emphasise red and blue from the start of the next.
+    let script = RegisterScript::parse(
+        "at dot 1 of scanline 241 read $2002\n\
+         at dot 5 of scanline 0 write $2001=$A0\n",
+    )
+    .unwrap();
+    let mut ppu = PpuSimulation::new(script);
+    ppu.set_output_mode(OutputMode::RawPixels);
+    ppu.run_script();
+    while ppu.position() != (2, 0) {
+        ppu.half_step();
+    }
+
+    let line = &ppu.raw_framebuffer()[256..512];
+    assert!(line.iter().all(|pixel| pixel >> 6 == 0b101));
+}
+
+#[test]
+fn event_log_records_register_accesses_and_memory_writes() {
+    let script = RegisterScript::parse(
+        "at dot 5 of scanline 0 write $2001=$00\n\
+         read $2002\n\
+         write $2006=$20\n\
+         write $2006=$00\n\
+         write $2007=$55\n",
+    )
+    .unwrap();
+    let mut ppu = PpuSimulation::new(script);
+    ppu.set_event_logging(true);
+    ppu.run_script();
+    for _ in 0..64 {
+        ppu.half_step();
+    }
+
+    let log = ppu.event_log().unwrap();
+    let accesses = log
+        .events()
+        .iter()
+        .filter_map(|event| match event.kind {
+            PpuEventKind::RegisterAccess { addr, data, write } => Some((addr, write, data)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(5, accesses.len());
+    assert_eq!((0x2001, true, 0x00), accesses[0]);
+    assert_eq!((0x2002, false), (accesses[1].0, accesses[1].1));
+    assert_eq!(
+        [
+            (0x2006, true, 0x20),
+            (0x2006, true, 0x00),
+            (0x2007, true, 0x55)
+        ],
+        accesses[2..]
+    );
+
+    // The PPU writes a byte of VRAM a few dots after `$2007` is written.
+    let events = log.events();
+    let data_write = events
+        .iter()
+        .position(|event| {
+            matches!(
+                event.kind,
+                PpuEventKind::RegisterAccess {
+                    addr: 0x2007,
+                    write: true,
+                    ..
+                }
+            )
+        })
+        .unwrap();
+    let memory_write = events[data_write..]
+        .iter()
+        .find(|event| matches!(event.kind, PpuEventKind::MemoryAccess { write: true, .. }))
+        .unwrap();
+    assert_eq!(
+        PpuEventKind::MemoryAccess {
+            addr: 0x2000,
+            data: 0x55,
+            write: true
+        },
+        memory_write.kind
+    );
+    assert!(memory_write.dot > events[data_write].dot);
+    assert_eq!(0, memory_write.scanline);
+    assert_eq!(log.events().len(), log.on_scanline(0, 0).count());
+
+    let mut text = Vec::new();
+    log.write_text(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert_eq!(log.events().len(), text.lines().count());
+    assert!(text.contains("CPU write $2007 = $55"));
+}
+
+#[test]
+fn cpu_only_program_runs_to_its_trap() {
+    let mut image = vec![0_u8; 0x10000];
+    #[rustfmt::skip]
+    let program = [
+        0xa2, 0x05,       // LDX #$05
+        0xa9, 0x00,       // LDA #$00
+        0x18,             // CLC
+        0x69, 0x03,       // ADC #$03
+        0xca,             // DEX
+        0xd0, 0xfa,       // BNE $0404
+        0x8d, 0x00, 0x02, // STA $0200
+        0x4c, 0x0d, 0x04, // JMP $040D
+    ];
+    image[0x400..0x400 + program.len()].copy_from_slice(&program);
+    image[0xfffc] = 0x00;
+    image[0xfffd] = 0x04;
+
+    let mut cpu = CpuSimulation::new(&image);
+    assert_eq!(Some(0x040d), cpu.run_until_trap(1000));
+    assert_eq!(15, cpu.ram()[0x200]);
+}
+
+/// Klaus Dormann's 6502 functional test, which isn't checked in. Build it from
+/// <https://github.com/Klaus2m5/6502_65C02_functional_tests> with `disable_decimal = 1` set in
+/// `6502_functional_test.a65`, as the 2A03 has no decimal mode:
+///
+/// ```text
+/// as65 -l -m -w -h0 6502_functional_test.a65
+/// ```
+///
+/// and copy the `.bin` and `.lst` it writes into `test_data`.
+const FUNCTIONAL_TEST_PATH: &str = "test_data/6502_functional_test.bin";
+/// The success trap moves with the options the test is built with, so it's found in the listing
+/// by the comment on the `success` macro's `JMP *`.
+const FUNCTIONAL_TEST_LISTING_PATH: &str = "test_data/6502_functional_test.lst";
+const FUNCTIONAL_TEST_SUCCESS_COMMENT: &str = ";test passed, no errors";
+
+// The test takes tens of millions of CPU cycles, hours even in release, so run it with
+// `cargo test --release functional_test -- --ignored`.
+#[test]
+#[ignore]
+fn cpu_only_functional_test_reaches_its_success_trap() {
+    let mut image = std::fs::read(FUNCTIONAL_TEST_PATH)
+        .expect("the functional test isn't built; see FUNCTIONAL_TEST_PATH");
+    let listing = std::fs::read_to_string(FUNCTIONAL_TEST_LISTING_PATH).unwrap();
+    let success = listing
+        .lines()
+        .find(|line| line.contains(FUNCTIONAL_TEST_SUCCESS_COMMENT))
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|addr| u16::from_str_radix(addr, 16).ok())
+        .expect("the listing has no success trap");
+    // The test starts at $0400. Its reset vector points at a trap.
+    image.resize(0x10000, 0);
+    image[0xfffc] = 0x00;
+    image[0xfffd] = 0x04;
+
+    let mut cpu = CpuSimulation::new(&image);
+    assert_eq!(Some(success), cpu.run_until_trap(200_000_000));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn parallel_recalc_matches_serial_recalc() {
+    let mut serial = scanline_sim(0);
+    let mut parallel = serial.clone();
+    parallel.set_parallel_recalc(true);
+    // Send every list through the thread pool, not just the large ones.
+    parallel.parallel_recalc.as_mut().unwrap().min_list_len = 1;
+
+    for _ in 0..2000 {
+        serial.half_step();
+        parallel.half_step();
+    }
+
+    verify_same_state(&serial, &parallel);
+}
+
+#[cfg(feature = "compiled")]
+#[test]
+fn compiled_tables_match_the_interpreter() {
+    let mut compiled = scanline_sim(0);
+    let mut interpreted = compiled.clone();
+    interpreted.set_compiled_tables(false);
+
+    for _ in 0..2000 {
+        compiled.half_step();
+        interpreted.half_step();
+    }
+
+    verify_same_state(&interpreted, &compiled);
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn stats_count_the_work_done_by_each_half_step() {
+    let mut sim = scanline_sim(0);
+    sim.stats_mut().reset();
+    run_half_steps(&mut sim, 10);
+    // The counts of each half-step are only kept when asked for.
+    assert_eq!(10, sim.stats().half_step_count);
+    assert!(sim.stats().half_steps.is_empty());
+    assert!(sim.stats().total.nodes_recalculated > 0);
+    assert!(sim.stats().last_half_step().nodes_recalculated > 0);
+
+    sim.stats_mut().reset();
+    sim.stats_mut().set_half_step_recording(true);
+    run_half_steps(&mut sim, 100);
+
+    let stats = sim.stats();
+    assert_eq!(100, stats.half_step_count);
+    assert_eq!(100, stats.half_steps.len());
+    assert_eq!(Some(&stats.last_half_step()), stats.half_steps.last());
+    let recalculated = stats
+        .half_steps
+        .iter()
+        .map(|counts| counts.nodes_recalculated)
+        .sum::<u64>();
+    assert!(recalculated > 0);
+    assert_eq!(recalculated, stats.total.nodes_recalculated);
+    assert_eq!(
+        stats.total.iterations,
+        stats
+            .half_steps
+            .iter()
+            .map(|counts| counts.iterations)
+            .sum::<u64>()
+    );
+    assert_eq!(
+        recalculated,
+        stats
+            .group_size_histogram()
+            .iter()
+            .map(|(_, count)| count)
+            .sum::<u64>()
+    );
+
+    let hottest = stats.hottest_nodes(10);
+    assert_eq!(10, hottest.len());
+    assert!(hottest.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    assert_eq!(hottest[0].1, stats.node_recalc_count(hottest[0].0));
+
+    let mut csv = Vec::new();
+    stats.write_half_steps_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(101, csv.lines().count());
+    assert!(csv.starts_with("half_step,nodes_recalculated,iterations,transistor_toggles\n"));
+}
+
+/// The ROMs whose first frames are checked against `GOLDEN_FRAMES_PATH`: the number of frames to
+/// run and whether to check the RAM as well as the picture.
+///
+/// Each ROM runs until it has turned rendering on and output a frame with it, which
+/// `scanline.nes` does from its seventh frame, and written to the RAM by then.
+const GOLDEN_ROMS: [(&str, u64, bool); 1] = [("scanline.nes", 7, true)];
+const GOLDEN_FRAMES_PATH: &str = "test_data/golden_frames.txt";
+/// Set this to rewrite the golden frames instead of checking them, after a change that's meant to
+/// alter the picture.
+const REWRITE_GOLDEN_FRAMES_VAR: &str = "NESSIM_REWRITE_GOLDEN_FRAMES";
+
+// A frame takes about a minute in release, so this is left out of the default test run. CI runs it
+// with `cargo test --release golden_frames -- --ignored`.
+#[test]
+#[ignore]
+fn golden_frames() {
+    let actual = GOLDEN_ROMS
+        .iter()
+        .flat_map(|(rom, frames, check_ram)| golden_frame_hashes(rom, *frames, *check_ram))
+        .collect::<Vec<String>>();
+
+    if std::env::var_os(REWRITE_GOLDEN_FRAMES_VAR).is_some() {
+        let mut contents = format!(
+            "# <rom> <frame> <pixels hash> [<RAM hash>], rewritten by setting {}\n",
+            REWRITE_GOLDEN_FRAMES_VAR
+        );
+        for line in &actual {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        std::fs::write(GOLDEN_FRAMES_PATH, contents).unwrap();
+        return;
+    }
+
+    let expected = read_golden_frames();
+    for (expected, actual) in expected.iter().zip(&actual) {
+        assert_eq!(
+            expected, actual,
+            "frame differs from {}; set {} to rewrite it if that's intended",
+            GOLDEN_FRAMES_PATH, REWRITE_GOLDEN_FRAMES_VAR
+        );
+    }
+    assert_eq!(expected.len(), actual.len());
+}
+
+#[test]
+fn golden_frames_cover_every_golden_rom() {
+    let expected = read_golden_frames();
+    for (rom, frames, check_ram) in GOLDEN_ROMS.iter() {
+        let lines = expected
+            .iter()
+            .map(|line| line.split(' ').collect::<Vec<&str>>())
+            .filter(|fields| fields[0] == *rom)
+            .collect::<Vec<_>>();
+        assert_eq!(*frames as usize, lines.len());
+        for (frame, fields) in lines.iter().enumerate() {
+            assert_eq!((frame + 1).to_string(), fields[1]);
+            assert_eq!(if *check_ram { 4 } else { 3 }, fields.len());
+        }
+    }
+}
+
+fn read_golden_frames() -> Vec<String> {
+    std::fs::read_to_string(GOLDEN_FRAMES_PATH)
+        .unwrap()
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Run a ROM from power-on for a number of frames and describe each one as it completes.
+fn golden_frame_hashes(rom: &str, frames: u64, check_ram: bool) -> Vec<String> {
+    let mut sim = SimulationState::new();
+    sim.load_rom(&mut File::open(format!("test_data/{}", rom)).unwrap());
+    sim.set_output_mode(OutputMode::RawPixels);
+
+    let mut lines = Vec::new();
+    while sim.frame_count() < frames {
+        sim.half_step();
+        if sim.is_frame_completed() {
+            let pixels = sim
+                .raw_framebuffer()
+                .iter()
+                .flat_map(|pixel| pixel.to_le_bytes());
+            let mut line = format!("{} {} {:016x}", rom, sim.frame_count(), fnv1a(pixels));
+            if check_ram {
+                let ram = sim
+                    .memory
+                    .cpu_ram
+                    .iter()
+                    .chain(sim.memory.nametable_ram.iter().flatten());
+                line.push_str(&format!(" {:016x}", fnv1a(ram.cloned())));
+            }
+            lines.push(line);
+        }
+    }
+    // The last frame has to show something the ROM rendered, not just the backdrop.
+    let first_pixel = sim.raw_framebuffer()[0];
+    assert!(sim
+        .raw_framebuffer()
+        .iter()
+        .any(|pixel| *pixel != first_pixel));
+    lines
+}
+
+/// Whether any cell of an OAM byte is connected to something other than the rest of its cell,
+/// which is only ever the PPU's bit lines.
+fn is_oam_byte_on_bit_lines(sim: &SimulationState, addr: usize) -> bool {
+    let netlist = &sim.netlist;
+    let cells = &netlist.sprite_nodes[addr];
+    let is_cell_node = |node_number: u16| {
+        cells
+            .iter()
+            .any(|(n0, n1)| i32::from(node_number) == *n0 || i32::from(node_number) == *n1)
+    };
+    cells
+        .iter()
+        .flat_map(|(n0, n1)| vec![*n0, *n1])
+        .filter(|node_number| *node_number >= 0)
+        .any(|node_number| {
+            let (start, end) = netlist.channel_range(node_number as usize);
+            (start as usize..end as usize).any(|i| {
+                let neighbor = netlist.channel_neighbors[i];
+                sim.transistor_on
+                    .get(netlist.channel_transistors[i] as usize)
+                    && neighbor != NODE_GND
+                    && neighbor != NODE_PWR
+                    && !is_cell_node(neighbor)
+            })
+        })
+}
+
+/// Power on with `scanline.nes` and run for `half_steps`.
+fn scanline_sim(half_steps: u32) -> SimulationState {
+    let mut sim = SimulationState::new();
+    sim.load_rom(&mut File::open("test_data/scanline.nes").unwrap());
+    run_half_steps(&mut sim, half_steps);
+    sim
+}
+
+fn run_half_steps(sim: &mut SimulationState, half_steps: u32) {
+    for _ in 0..half_steps {
+        sim.half_step();
+    }
+}
+
+fn fnv1a<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn verify_same_state(expected: &SimulationState, actual: &SimulationState) {
+    assert!(
+        expected.node_state == actual.node_state,
+        "node state mismatch"
+    );
+    assert!(
+        expected.node_pullup == actual.node_pullup,
+        "node pullup mismatch"
+    );
+    assert!(
+        expected.node_pulldown == actual.node_pulldown,
+        "node pulldown mismatch"
+    );
+    assert!(
+        expected.transistor_on == actual.transistor_on,
+        "transistor state mismatch"
+    );
+    assert_eq!(&expected.memory.cpu_ram[..], &actual.memory.cpu_ram[..]);
+    assert_eq!(&expected.memory.chr_ram[..], &actual.memory.chr_ram[..]);
+    assert_eq!(
+        &expected.memory.nametable_ram[..],
+        &actual.memory.nametable_ram[..]
+    );
+    assert_eq!(expected.chr_address, actual.chr_address);
+    assert_eq!(expected.last_data, actual.last_data);
+    assert_eq!(&expected.ppu_framebuffer[..], &actual.ppu_framebuffer[..]);
+    assert_eq!(&expected.raw_framebuffer[..], &actual.raw_framebuffer[..]);
+    assert_eq!(expected.frame_count(), actual.frame_count());
+    assert_eq!(
+        expected.event_log().map(|log| log.events()),
+        actual.event_log().map(|log| log.events())
+    );
+    assert_eq!(expected.power_on_options(), actual.power_on_options());
+    assert_eq!(expected.clock_alignment(), actual.clock_alignment());
+    assert_eq!(
+        expected.reset_hold_half_steps(),
+        actual.reset_hold_half_steps()
+    );
+}
+
 fn verify_ram_state(sim: &SimulationState, reference_prg: &[u8], reference_chr: &[u8]) {
-    assert_eq!(reference_prg.len(), sim.prg_ram.len());
-    assert_eq!(reference_chr.len(), sim.chr_ram.len());
+    assert_eq!(reference_prg.len(), sim.memory.prg_ram.len());
+    assert_eq!(reference_chr.len(), sim.memory.chr_ram.len());
 
     for (i, byte) in reference_prg.iter().enumerate() {
         assert_eq!(
-            *byte, sim.prg_ram[i],
+            *byte, sim.memory.prg_ram[i],
             "PRG RAM value mismatch at index {}",
             i
         );
@@ -66,7 +899,7 @@ fn verify_ram_state(sim: &SimulationState, reference_prg: &[u8], reference_chr:
 
     for (i, byte) in reference_chr.iter().enumerate() {
         assert_eq!(
-            *byte, sim.chr_ram[i],
+            *byte, sim.memory.chr_ram[i],
             "CHR RAM value mismatch at index {}",
             i
         );
@@ -90,7 +923,7 @@ fn verify_state<R: Read>(sim: &SimulationState, reader: &mut R) {
 
     assert_eq!(
         reference_nodes.len(),
-        sim.nodes.len(),
+        sim.netlist.node_count(),
         "reference node count != node count"
     );
 
@@ -106,60 +939,49 @@ fn verify_state<R: Read>(sim: &SimulationState, reader: &mut R) {
 
     assert_eq!(
         reference_transistors.len(),
-        sim.transistors.len(),
+        sim.netlist.transistor_count(),
         "reference transistors count {} != transistors count {}",
         reference_transistors.len(),
-        sim.transistors.len()
+        sim.netlist.transistor_count()
     );
 
     for (i, reference_node) in reference_nodes.iter().enumerate() {
-        let (floating, pulldown, pullup, state) = *reference_node;
-        let node = &sim.nodes[i];
-
-        assert_eq!(
-            floating,
-            node.floating.get(),
-            "Floating expected was {} but was {} at node {}",
-            floating,
-            node.floating.get(),
-            i
-        );
-
+        let (_, pulldown, pullup, state) = *reference_node;
         assert_eq!(
             pullup,
-            node.pullup.get(),
+            sim.node_pullup.get(i),
             "Pullup expected was {} but was {} at node {}",
             pullup,
-            node.pullup.get(),
+            sim.node_pullup.get(i),
             i
         );
 
         assert_eq!(
             pullup,
-            node.pullup.get(),
+            sim.node_pullup.get(i),
             "Pulldown expected was {} but was {} at node {}",
             pulldown,
-            node.pulldown.get(),
+            sim.node_pulldown.get(i),
             i
         );
 
         assert_eq!(
             state,
-            node.state.get(),
+            sim.node_state.get(i),
             "State expected was {} but was {} at node {}",
             state,
-            node.state.get(),
+            sim.node_state.get(i),
             i
         );
     }
     for (i, reference_transistor) in reference_transistors.iter().enumerate() {
         assert_eq!(
             *reference_transistor,
-            sim.transistors[i].on.get(),
+            sim.transistor_on.get(i),
             "Expected transistor {} to be {}, was {}",
             i,
             reference_transistor,
-            sim.transistors[i].on.get()
+            sim.transistor_on.get(i)
         );
     }
 }
@@ -0,0 +1,166 @@
+//! Pictures of the PPU's memory, like the viewers in an emulator's debugger. The palette and OAM
+//! are read out of their cells in the netlist, so they show what the chip really holds.
+
+use crate::{
+    consts::{PALETTE_RAM_SIZE, SPRITE_RAM_SIZE},
+    image::Image,
+    SimulationState,
+};
+
+/// The width and height of a pattern table, 16 tiles square.
+pub const PATTERN_TABLE_SIZE: usize = 128;
+/// The number of sprites in primary OAM.
+const SPRITES: usize = 64;
+/// The size of each colour's square in the palette view.
+const SWATCH_SIZE: usize = 8;
+
+impl SimulationState {
+    /// The palette RAM, read from its cells. The sprite palettes' backdrop entries mirror the
+    /// background palettes'.
+    pub fn palette_ram(&self) -> [u8; PALETTE_RAM_SIZE] {
+        let mut ram = [0; PALETTE_RAM_SIZE];
+        for (addr, val) in ram.iter_mut().enumerate() {
+            *val = self.palette_read(addr as u16);
+        }
+        ram
+    }
+
+    /// Primary OAM followed by the 32 bytes of secondary OAM, read from their cells. The unused
+    /// bits of the attribute bytes have no cells and read as 0.
+    pub fn oam(&self) -> [u8; SPRITE_RAM_SIZE] {
+        let mut oam = [0; SPRITE_RAM_SIZE];
+        for (addr, val) in oam.iter_mut().enumerate() {
+            *val = self.sprite_read(addr as u16);
+        }
+        oam
+    }
+
+    /// Draw pattern table 0 or 1 from the CHR RAM as 16x16 tiles, coloured with one of the eight
+    /// palettes, the sprite palettes being 4 to 7.
+    pub fn render_pattern_table(&self, table: usize, palette: usize) -> Image {
+        assert!(table < 2, "there are only two pattern tables");
+        let colours = self.palette_colours();
+        let mut image = Image::new(PATTERN_TABLE_SIZE, PATTERN_TABLE_SIZE);
+        for tile in 0..256 {
+            let (tile_x, tile_y) = ((tile & 0x0f) * 8, (tile >> 4) * 8);
+            self.draw_tile(
+                &mut image,
+                tile_x,
+                tile_y,
+                table * 0x100 + tile,
+                |x, y, pixel| Some((x, y, colours[palette * 4 + pixel])),
+            );
+        }
+        image
+    }
+
+    /// Draw the four nametables as a 512x480 map, as the current mirroring places them, with
+    /// their tiles from a pattern table and colours from their attributes.
+    pub fn render_nametables(&self, pattern_table: usize) -> Image {
+        assert!(pattern_table < 2, "there are only two pattern tables");
+        let colours = self.palette_colours();
+        let mut image = Image::new(512, 480);
+        for nametable in 0..4 {
+            let base = 0x2000 + nametable as u16 * 0x400;
+            let (left, top) = ((nametable & 1) * 256, (nametable >> 1) * 240);
+            for row in 0..30 {
+                for column in 0..32 {
+                    let tile = self.memory.ppu_read(base + row * 32 + column) as usize;
+                    let attribute = self
+                        .memory
+                        .ppu_read(base + 0x3c0 + (row / 4) * 8 + column / 4);
+                    let shift = ((row & 2) << 1) | (column & 2);
+                    let palette = ((attribute >> shift) & 0x03) as usize;
+                    let (tile_x, tile_y) = (left + column as usize * 8, top + row as usize * 8);
+                    self.draw_tile(
+                        &mut image,
+                        tile_x,
+                        tile_y,
+                        pattern_table * 0x100 + tile,
+                        |x, y, pixel| Some((x, y, colours[palette * 4 + pixel])),
+                    );
+                }
+            }
+        }
+        image
+    }
+
+    /// Draw the 64 sprites in OAM as a 64x64 grid of their 8x8 tiles from a pattern table, flipped
+    /// and coloured as their attributes say. Their transparent pixels are left with no alpha.
+    pub fn render_oam(&self, pattern_table: usize) -> Image {
+        assert!(pattern_table < 2, "there are only two pattern tables");
+        let colours = self.palette_colours();
+        let oam = self.oam();
+        let mut image = Image::new(64, 64);
+        for (sprite, entry) in oam.chunks(4).take(SPRITES).enumerate() {
+            let (tile, attributes) = (entry[1] as usize, entry[2]);
+            let palette = 4 + (attributes & 0x03) as usize;
+            let (flip_x, flip_y) = (attributes & 0x40 != 0, attributes & 0x80 != 0);
+            let (tile_x, tile_y) = ((sprite & 0x07) * 8, (sprite >> 3) * 8);
+            self.draw_tile(
+                &mut image,
+                tile_x,
+                tile_y,
+                pattern_table * 0x100 + tile,
+                |x, y, pixel| {
+                    let x = if flip_x { 7 - x } else { x };
+                    let y = if flip_y { 7 - y } else { y };
+                    if pixel == 0 {
+                        None
+                    } else {
+                        Some((x, y, colours[palette * 4 + pixel]))
+                    }
+                },
+            );
+        }
+        image
+    }
+
+    /// Draw the 32 palette entries as two rows of 8x8 squares, the background palettes above the
+    /// sprite palettes.
+    pub fn render_palette(&self) -> Image {
+        let palette_ram = self.palette_ram();
+        let mut image = Image::new(16 * SWATCH_SIZE, 2 * SWATCH_SIZE);
+        for (addr, val) in palette_ram.iter().enumerate() {
+            let colour = self.palette.argb(u16::from(*val));
+            let (left, top) = ((addr & 0x0f) * SWATCH_SIZE, (addr >> 4) * SWATCH_SIZE);
+            for y in top..top + SWATCH_SIZE {
+                for x in left..left + SWATCH_SIZE {
+                    image.set_pixel(x, y, colour);
+                }
+            }
+        }
+        image
+    }
+
+    /// The colour of each of the 32 palette entries as the PPU would output it, with pixel value 0
+    /// of every palette showing the backdrop.
+    fn palette_colours(&self) -> [u32; PALETTE_RAM_SIZE] {
+        let palette_ram = self.palette_ram();
+        let mut colours = [0; PALETTE_RAM_SIZE];
+        for (index, colour) in colours.iter_mut().enumerate() {
+            let entry = if index & 0x03 == 0 { 0 } else { index };
+            *colour = self.palette.argb(u16::from(palette_ram[entry]));
+        }
+        colours
+    }
+
+    /// Draw one of the 512 tiles in the CHR RAM with its top left at `(left, top)`. `place` is
+    /// given each pixel's position in the tile and its 2-bit value, and says where in the tile to
+    /// draw it and in what colour, if at all.
+    fn draw_tile<F>(&self, image: &mut Image, left: usize, top: usize, tile: usize, place: F)
+    where
+        F: Fn(usize, usize, usize) -> Option<(usize, usize, u32)>,
+    {
+        let planes = &self.memory.chr_ram[tile * 16..tile * 16 + 16];
+        for y in 0..8 {
+            for x in 0..8 {
+                let bit = 7 - x;
+                let pixel = ((planes[y] >> bit) & 1) | (((planes[y + 8] >> bit) & 1) << 1);
+                if let Some((x, y, colour)) = place(x, y, pixel as usize) {
+                    image.set_pixel(left + x, top + y, colour);
+                }
+            }
+        }
+    }
+}